@@ -3,8 +3,11 @@
 use std::{
     any::Any,
     collections::HashMap,
+    future::Future,
     ops::Deref,
+    pin::Pin,
     sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
 };
 
 pub use bugi_share::*;
@@ -20,6 +23,93 @@ pub trait PluginSystem: Send + Sync {
         abi: u64,
         ploxy: EnvPloxy,
     ) -> Result<Vec<u8>, BugiError>;
+
+    /// call a plugin function without blocking an async executor.
+    /// The default runs `raw_call` inline, so it blocks until the call returns.
+    fn raw_call_async<'a>(
+        &'a self,
+        symbol: &'a str,
+        param: &'a [u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> BoxFuture<'a, Result<Vec<u8>, BugiError>> {
+        Box::pin(async move { self.raw_call(symbol, param, abi, ploxy) })
+    }
+
+    /// functions the plugin exposes, empty if it can't list them
+    fn symbols(&self) -> Vec<PluginSymbol> {
+        Vec::new()
+    }
+
+    /// drop any state the plugin keeps between calls
+    fn reset(&self) -> Result<(), BugiError> {
+        Ok(())
+    }
+
+    /// what the plugin declares about itself, empty if it declares nothing
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata::default()
+    }
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Run a future to completion on the current thread.
+/// Used where a synchronous call meets an async implementation.
+/// This is no executor: calling it from an async task blocks that task's thread,
+/// and a future that needs a runtime's reactor or timers never completes or panics.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark()
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// A span entered for the time of a plugin call.
+/// Records the `duration_us` field of the span when dropped.
+#[cfg(feature = "tracing")]
+pub struct CallSpan {
+    span: tracing::span::EnteredSpan,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl CallSpan {
+    pub fn enter(span: tracing::Span) -> Self {
+        Self {
+            span: span.entered(),
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for CallSpan {
+    fn drop(&mut self) {
+        self.span
+            .record("duration_us", self.start.elapsed().as_micros() as u64);
+    }
+}
+
+/// A function exposed by a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginSymbol {
+    pub name: String,
+    /// ABI ID the function accepts, `None` if the plugin decides it at call time
+    pub abi: Option<u64>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -44,11 +134,197 @@ pub enum BugiError {
 
     #[error("plugin universe dropped")]
     PluginUniverseDropped,
+
+    #[error("plugin load error: {0}")]
+    PluginLoadError(String),
+
+    #[error("plugin ID mismatch: expected = {0}, found = {1}")]
+    PluginIdMismatch(String, String),
+
+    #[error("plugin trapped in {id}:{symbol}: {message}")]
+    PluginTrap {
+        id: String,
+        symbol: String,
+        message: String,
+    },
+
+    #[error("plugin panicked in {id}:{symbol} at {location}: {message}")]
+    PluginPanicked {
+        id: String,
+        symbol: String,
+        message: String,
+        location: String,
+    },
+
+    #[error("plugin exceeded the {limit} limit in {id}:{symbol}")]
+    ExecutionLimitExceeded {
+        id: String,
+        symbol: String,
+        limit: ExecutionLimit,
+    },
+
+    #[error("plugin exceeded a resource limit in {id}:{symbol}: {message}")]
+    ResourceLimitExceeded {
+        id: String,
+        symbol: String,
+        message: String,
+    },
+
+    #[error("plugin process exited in {id}:{symbol}: {status}")]
+    PluginExited {
+        id: String,
+        symbol: String,
+        status: String,
+    },
+
+    #[error("call depth limit of {limit} exceeded: {chain}")]
+    CallDepthExceeded { limit: usize, chain: CallChain },
+
+    /// A function was called again, with the same ABI and arguments, inside its own call.
+    /// This also rejects recursion that would end because the plugin keeps state between calls;
+    /// such a plugin has to vary its arguments, e.g. pass a counter.
+    #[error("call cycle detected: {chain}")]
+    CallCycle { chain: CallChain },
+
+    #[error("permission denied: {caller} may not call {plugin}:{symbol}")]
+    PermissionDenied {
+        caller: String,
+        plugin: String,
+        symbol: String,
+    },
+}
+
+/// Execution limits of a plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionLimit {
+    /// fuel available to a single call
+    CallFuel,
+    /// fuel available until the plugin is reset
+    TotalFuel,
+    /// wall-clock time of a single call
+    Deadline,
+}
+
+impl std::fmt::Display for ExecutionLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionLimit::CallFuel => write!(f, "fuel per call"),
+            ExecutionLimit::TotalFuel => write!(f, "total fuel"),
+            ExecutionLimit::Deadline => write!(f, "deadline"),
+        }
+    }
 }
 
 /// Plugin Reference ID
 pub type PluginId = u32;
 
+/// What a plugin declares about itself, readable before it's called
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PluginMetadata {
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    /// string IDs of the plugins it calls
+    pub dependencies: Vec<String>,
+    /// calls it needs to be granted
+    pub capabilities: Vec<Capability>,
+    /// functions it exports
+    pub symbols: Vec<SymbolSignature>,
+}
+
+/// Declared signature of a plugin function, with the types as written in the plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolSignature {
+    pub name: String,
+    pub params: Vec<String>,
+    pub result: String,
+}
+
+/// A call a plugin is allowed to make
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    /// string ID of the plugin to call
+    pub plugin: String,
+    /// symbols to call, `*` matches any run of characters
+    pub symbol: String,
+}
+
+impl Capability {
+    pub fn new(plugin: &str, symbol: &str) -> Self {
+        Self {
+            plugin: plugin.to_string(),
+            symbol: symbol.to_string(),
+        }
+    }
+
+    /// Whether the capability covers a call of `plugin:symbol`
+    pub fn allows(&self, plugin: &str, symbol: &str) -> bool {
+        self.plugin == plugin && glob_match(&self.symbol, symbol)
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((head, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut text) = text.strip_prefix(head) else {
+        return false;
+    };
+    let mut parts = rest.split('*').collect::<Vec<_>>();
+    let tail = parts.pop().unwrap_or("");
+    for part in parts {
+        match text.find(part) {
+            Some(pos) => text = &text[pos + part.len()..],
+            None => return false,
+        }
+    }
+    text.ends_with(tail)
+}
+
+/// A plugin function on a call chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallFrame {
+    pub plugin_id: String,
+    pub symbol: String,
+}
+
+/// Plugin functions a call went through, outermost first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallChain(pub Vec<CallFrame>);
+
+impl std::fmt::Display for CallChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, frame) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " -> ")?;
+            }
+            write!(f, "{}:{}", frame.plugin_id, frame.symbol)?;
+        }
+        Ok(())
+    }
+}
+
+/// A log record written by a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub plugin_id: String,
+    pub symbol: String,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// Receives the log records of plugins
+pub trait LogSink: Send + Sync {
+    fn log(&self, record: LogRecord);
+}
+
+impl<F: Fn(LogRecord) + Send + Sync> LogSink for F {
+    fn log(&self, record: LogRecord) {
+        self(record)
+    }
+}
+
 pub type CacheData = Box<dyn Any + Send + Sync>;
 
 #[derive(Default, Clone)]
@@ -89,7 +365,20 @@ impl Cacher {
 
 /// Multithread Sharing & Using is not Safety
 #[derive(Clone)]
-pub struct EnvPloxy(Arc<EnvPloxyInner>);
+pub struct EnvPloxy {
+    inner: Arc<EnvPloxyInner>,
+
+    /// calls this ploxy is inside of, outermost first
+    chain: Arc<Vec<ChainEntry>>,
+}
+
+/// A call on the chain of a ploxy, with what it was called with
+#[derive(Clone, PartialEq)]
+struct ChainEntry {
+    frame: CallFrame,
+    abi: u64,
+    arg: Arc<[u8]>,
+}
 
 pub type CallUnivSig = dyn (Fn(
         /*plugin id=*/ &str,
@@ -101,43 +390,134 @@ pub type CallUnivSig = dyn (Fn(
     + Send
     + Sync;
 
+pub type CallUnivAsyncSig = dyn (Fn(
+        /*plugin id=*/ String,
+        /*symbol=*/ String,
+        /*arg=*/ Vec<u8>,
+        /*abi id=*/ u64,
+        /*ploxy=*/ EnvPloxy,
+    ) -> BoxFuture<'static, Result<Vec<u8>, BugiError>>)
+    + Send
+    + Sync;
+
 struct EnvPloxyInner {
     pub cache: Option<CachePloxy>,
 
     pub call_univ: Box<CallUnivSig>,
+
+    /// `None` makes async calls fall back to `call_univ`
+    pub call_univ_async: Option<Box<CallUnivAsyncSig>>,
+
+    pub log_sink: Option<Arc<dyn LogSink>>,
 }
 
 impl EnvPloxy {
-    pub fn new(cacher: Option<&Cacher>, call_univ: Box<CallUnivSig>, plug_id: PluginId) -> Self {
-        Self(Arc::new(EnvPloxyInner {
-            cache: cacher.map(|cacher| CachePloxy {
-                get_global: {
-                    let cacher = cacher.clone();
-                    Box::new(move |str| cacher.pop_global(str))
-                },
-                set_global: {
-                    let cacher = cacher.clone();
-                    Box::new(move |str, data| cacher.push_global(str, data))
-                },
-                get_cache: {
-                    let cacher = cacher.clone();
-                    Box::new(move || cacher.pop(plug_id))
-                },
-                set_cache: {
-                    let cacher = cacher.clone();
-                    Box::new(move |data| cacher.push(plug_id, data))
-                },
+    pub fn new(
+        cacher: Option<&Cacher>,
+        call_univ: Box<CallUnivSig>,
+        plug_id: PluginId,
+        log_sink: Option<Arc<dyn LogSink>>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(EnvPloxyInner {
+                cache: cacher.map(|cacher| CachePloxy {
+                    get_global: {
+                        let cacher = cacher.clone();
+                        Box::new(move |str| cacher.pop_global(str))
+                    },
+                    set_global: {
+                        let cacher = cacher.clone();
+                        Box::new(move |str, data| cacher.push_global(str, data))
+                    },
+                    get_cache: {
+                        let cacher = cacher.clone();
+                        Box::new(move || cacher.pop(plug_id))
+                    },
+                    set_cache: {
+                        let cacher = cacher.clone();
+                        Box::new(move |data| cacher.push(plug_id, data))
+                    },
+                }),
+                call_univ,
+                call_univ_async: None,
+                log_sink,
             }),
-            call_univ,
-        }))
+            chain: Arc::default(),
+        }
+    }
+
+    /// Set how async calls reach the universe. Must be called on a new ploxy, before it's cloned.
+    pub fn with_call_univ_async(mut self, call_univ_async: Box<CallUnivAsyncSig>) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("the ploxy is already shared")
+            .call_univ_async = Some(call_univ_async);
+        self
+    }
+
+    /// Plugin functions the current call went through, outermost first
+    pub fn call_chain(&self) -> CallChain {
+        CallChain(self.chain.iter().map(|entry| entry.frame.clone()).collect())
+    }
+
+    /// The function running with this ploxy, `None` outside of plugin calls
+    pub fn caller(&self) -> Option<&CallFrame> {
+        self.chain.last().map(|entry| &entry.frame)
+    }
+
+    /// Ploxy for a call of `plugin_id:symbol` made inside the current one.
+    /// Fails if the chain gets longer than `max_depth`,
+    /// or if the function is already on the chain with the same arguments, as that never ends.
+    pub fn enter(
+        &self,
+        plugin_id: &str,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+        max_depth: usize,
+    ) -> Result<EnvPloxy, BugiError> {
+        let entry = ChainEntry {
+            frame: CallFrame {
+                plugin_id: plugin_id.to_string(),
+                symbol: symbol.to_string(),
+            },
+            abi,
+            arg: arg.into(),
+        };
+
+        let cycle = self.chain.contains(&entry);
+        let mut chain = Vec::clone(&self.chain);
+        chain.push(entry);
+        let ploxy = EnvPloxy {
+            inner: self.inner.clone(),
+            chain: Arc::new(chain),
+        };
+        if cycle {
+            Err(BugiError::CallCycle {
+                chain: ploxy.call_chain(),
+            })
+        } else if ploxy.chain.len() > max_depth {
+            Err(BugiError::CallDepthExceeded {
+                limit: max_depth,
+                chain: ploxy.call_chain(),
+            })
+        } else {
+            Ok(ploxy)
+        }
+    }
+
+    /// Pass a record to the log sink of the universe, if any
+    pub fn log(&self, record: LogRecord) {
+        if let Some(sink) = self.inner.log_sink.as_ref() {
+            sink.log(record)
+        }
     }
 
     pub fn get_cache(&self) -> Option<CacheData> {
-        self.0.cache.as_ref().map(|c| (c.get_cache)())?
+        self.inner.cache.as_ref().map(|c| (c.get_cache)())?
     }
 
     pub fn set_cache(&self, data: CacheData) {
-        if let Some(c) = self.0.cache.as_ref() {
+        if let Some(c) = self.inner.cache.as_ref() {
             (c.set_cache)(data)
         }
     }
@@ -149,7 +529,19 @@ impl EnvPloxy {
         arg: &[u8],
         abi: u64,
     ) -> Result<Vec<u8>, BugiError> {
-        (self.0.call_univ)(str, symbol, arg, abi, EnvPloxy(self.0.clone()))
+        #[cfg(feature = "tracing")]
+        let _span = CallSpan::enter(tracing::debug_span!(
+            "call_univ",
+            plugin = str,
+            symbol,
+            abi,
+            payload = arg.len(),
+            caller = self
+                .caller()
+                .map(|frame| format!("{}:{}", frame.plugin_id, frame.symbol)),
+            duration_us = tracing::field::Empty,
+        ));
+        (self.inner.call_univ)(str, symbol, arg, abi, self.clone())
     }
 
     pub fn call_univ<S: SerializeTag, Output: FromByte<S>>(
@@ -166,12 +558,46 @@ impl EnvPloxy {
         )?)?)
     }
 
+    pub fn call_univ_async_raw(
+        &self,
+        str: &str,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+    ) -> BoxFuture<'static, Result<Vec<u8>, BugiError>> {
+        match self.inner.call_univ_async.as_ref() {
+            Some(call_univ_async) => call_univ_async(
+                str.to_string(),
+                symbol.to_string(),
+                arg.to_vec(),
+                abi,
+                self.clone(),
+            ),
+            None => {
+                let res = self.call_univ_raw(str, symbol, arg, abi);
+                Box::pin(async move { res })
+            }
+        }
+    }
+
+    pub fn call_univ_async<S: SerializeTag, Output: FromByte<S>>(
+        &self,
+        str: &str,
+        symbol: &str,
+        args: impl ParamListTo<S>,
+    ) -> impl Future<Output = Result<Output, BugiError>> + Send + 'static {
+        let call = args
+            .to_byte()
+            .map(|arg| self.call_univ_async_raw(str, symbol, &arg, S::get_abi_id()));
+        async move { Ok(Output::from_byte(&call?.await?)?) }
+    }
+
     pub fn get_global(&self, str: &str) -> Option<CacheData> {
-        self.0.cache.as_ref().and_then(|c| (c.get_global)(str))
+        self.inner.cache.as_ref().and_then(|c| (c.get_global)(str))
     }
 
     pub fn set_global(&self, str: &str, data: CacheData) {
-        if let Some(c) = self.0.cache.as_ref() {
+        if let Some(c) = self.inner.cache.as_ref() {
             (c.set_global)(str, data)
         }
     }
@@ -180,8 +606,8 @@ impl EnvPloxy {
 /// Multithread Sharing & Using is not Safety
 pub struct CachePloxy {
     pub get_global: Box<dyn (Fn(&str) -> Option<CacheData>) + Send + Sync>,
-    pub set_global: Box<dyn (Fn(&str, CacheData)) + Send + Sync>,
+    pub set_global: Box<dyn Fn(&str, CacheData) + Send + Sync>,
 
     pub get_cache: Box<dyn (Fn() -> Option<CacheData>) + Send + Sync>,
-    pub set_cache: Box<dyn (Fn(CacheData)) + Send + Sync>,
+    pub set_cache: Box<dyn Fn(CacheData) + Send + Sync>,
 }
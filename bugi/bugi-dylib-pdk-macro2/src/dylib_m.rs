@@ -0,0 +1,101 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, ToTokens};
+use syn::{parse2, FnArg, ItemFn, LitStr, ReturnType};
+
+const DYLIB_SPEC_FUNC: &str = "bugi_v0_plugin_function_";
+const DYLIB_SPEC_ID: &str = "bugi_v0_plugin_id";
+
+pub fn dylib_export_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let (name, abi_type) = {
+        let name;
+
+        let mut iter = attr.into_iter().fuse();
+        match iter.next() {
+            Some(arg) => {
+                name = parse2::<LitStr>(arg.into()).unwrap();
+            }
+            None => {
+                panic!("not found first arg")
+            }
+        }
+
+        let _ = iter.next();
+
+        let ident = iter.collect::<TokenStream>();
+        (name, ident)
+    };
+
+    let fn_item = parse2::<ItemFn>(item.clone()).unwrap();
+    let arg_types = fn_item
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|a| match a {
+            FnArg::Typed(ptype) => Some(&ptype.ty),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let fn_name = fn_item.sig.ident;
+    let call_token = if arg_types.is_empty() {
+        quote! {
+            let _ = arg;
+            let res = #fn_name();
+        }
+    } else {
+        let i = (0..arg_types.len()).map(syn::Index::from);
+        quote! {
+            type ArgTuple = (#(#arg_types),*,);
+            let arg: ArgTuple = <ArgTuple as FromByte<#abi_type>>::from_byte(arg)
+                .map_err(|err| format!("can't deserialize the argument: {}", err))?;
+            let res = #fn_name(#(arg.#i),*);
+        }
+    };
+
+    let return_type = match fn_item.sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ref ty) => ty.to_token_stream(),
+    };
+
+    let fn_name_ident = format_ident!("__bugi_func_{}", &name.value());
+    let fn_name_export = format!("{}{}", DYLIB_SPEC_FUNC, &name.value());
+    quote! {
+        #[export_name = #fn_name_export]
+        unsafe extern "C" fn #fn_name_ident(
+            host: *const ::bugi_dylib_pdk::macro_prelude::ffi::DylibHost,
+            arg_ptr: *mut u8,
+            arg_len: usize,
+            abi_type: u64,
+            out_len: *mut usize,
+        ) -> *mut u8 {
+            use ::bugi_dylib_pdk::macro_prelude::*;
+            ::bugi_dylib_pdk::run(host, arg_ptr, arg_len, out_len, |arg| {
+                if <#abi_type as SerializeTag>::get_abi_id() != abi_type {
+                    return Err(format!(
+                        "ABI Type(id: {}) is not match this function(id: {})",
+                        abi_type,
+                        <#abi_type as SerializeTag>::get_abi_id()
+                    ));
+                }
+                #call_token
+                type ReturnType = #return_type;
+                <ReturnType as ToByte<#abi_type>>::to_byte(&res)
+                    .map_err(|err| format!("can't serialize the result: {}", err))
+            })
+        }
+
+        #item
+    }
+}
+
+pub fn dylib_plugin_id_macro(input: TokenStream) -> TokenStream {
+    let name = parse2::<LitStr>(input).unwrap();
+    quote! {
+        #[export_name = #DYLIB_SPEC_ID]
+        unsafe extern "C" fn __bugi_plugin_id(out_len: *mut usize) -> *const u8 {
+            const ID: &str = #name;
+            *out_len = ID.len();
+            ID.as_ptr()
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod dylib_m;
+pub use crate::dylib_m::{dylib_export_macro, dylib_plugin_id_macro};
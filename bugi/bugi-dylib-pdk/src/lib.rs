@@ -0,0 +1,178 @@
+use std::alloc::Layout;
+use std::cell::{Cell, RefCell};
+use std::panic::AssertUnwindSafe;
+use std::ptr::NonNull;
+use std::sync::Once;
+
+use bugi_share::ffi::DylibHost;
+use bugi_share::FromByte;
+use bugi_share::ParamListTo;
+use bugi_share::SerializeError;
+use bugi_share::SerializeTag;
+use bugi_share::ERROR_ABI_ID;
+
+pub use bugi_dylib_pdk_macro::export;
+pub use bugi_dylib_pdk_macro::plugin_id;
+pub use bugi_pdk_share::{debug, error, info, log, trace, warn};
+pub use bugi_share::LogLevel;
+
+pub mod macro_prelude {
+    pub use bugi_share::*;
+}
+
+thread_local! {
+    /// host of the running plugin function
+    static HOST: Cell<*const DylibHost> = const { Cell::new(std::ptr::null()) };
+
+    /// location of the last panic on this thread
+    static PANIC_LOCATION: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CallError {
+    #[error("cannot serialize: {0}")]
+    CannotSerialize(#[from] SerializeError),
+
+    #[error("call_univ error: {0}")]
+    Univ(String),
+}
+
+/// The host of the running plugin function.
+/// Panics outside of a plugin function, where there is no host to talk to.
+fn host() -> &'static DylibHost {
+    let host = HOST.get();
+    assert!(!host.is_null(), "not in a plugin function");
+    unsafe { &*host }
+}
+
+pub fn call<SType: SerializeTag, Output: FromByte<SType>>(
+    id: &str,
+    symbol: &str,
+    param: impl ParamListTo<SType>,
+) -> Result<Output, CallError> {
+    let arg = param.to_byte()?;
+    let host = host();
+
+    let (mut out_abi, mut out_len) = (0, 0);
+    let res = unsafe {
+        let out = (host.call_univ)(
+            host.ctx,
+            id.as_ptr(),
+            id.len(),
+            symbol.as_ptr(),
+            symbol.len(),
+            arg.as_ptr(),
+            arg.len(),
+            SType::get_abi_id(),
+            &mut out_abi,
+            &mut out_len,
+        );
+        let res = std::slice::from_raw_parts(out, out_len).to_vec();
+        dealloc(out, out_len);
+        res
+    };
+
+    if out_abi == ERROR_ABI_ID {
+        return Err(CallError::Univ(String::from_utf8_lossy(&res).into_owned()));
+    }
+
+    Ok(Output::from_byte(&res)?)
+}
+
+/// Write a log record to the host's log sink.
+/// Outside of a plugin function there is no host, and the record is dropped.
+pub fn log(level: LogLevel, message: &str) {
+    let host = HOST.get();
+    if host.is_null() {
+        return;
+    }
+    let host = unsafe { &*host };
+    unsafe { (host.log)(host.ctx, level as u32, message.as_ptr(), message.len()) }
+}
+
+/// Remember where panics happen, `run` reports it with the message.
+/// Also points the log macros at the host.
+fn set_panic_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        bugi_pdk_share::set_log_sink(log);
+        std::panic::set_hook(Box::new(|info| {
+            let location = info
+                .location()
+                .map(|location| location.to_string())
+                .unwrap_or_default();
+            PANIC_LOCATION.set(location);
+        }))
+    });
+}
+
+/// Body of the functions generated by `#[export]`.
+/// Panics and errors of `func` are reported to the host, and the function returns null.
+///
+/// # Safety
+/// The arguments must be the ones the host passed to the plugin function.
+#[doc(hidden)]
+pub unsafe fn run(
+    host: *const DylibHost,
+    arg_ptr: *mut u8,
+    arg_len: usize,
+    out_len: *mut usize,
+    func: impl FnOnce(&[u8]) -> Result<Vec<u8>, String>,
+) -> *mut u8 {
+    set_panic_hook();
+    // plugin functions may be re-entered through `call`
+    let outer = HOST.replace(host);
+
+    let arg = std::slice::from_raw_parts(arg_ptr, arg_len).to_vec();
+    dealloc(arg_ptr, arg_len);
+
+    let (message, location) = match std::panic::catch_unwind(AssertUnwindSafe(|| func(&arg))) {
+        Ok(Ok(res)) => {
+            HOST.set(outer);
+            let ptr = alloc(res.len());
+            std::ptr::copy_nonoverlapping(res.as_ptr(), ptr, res.len());
+            *out_len = res.len();
+            return ptr;
+        }
+        Ok(Err(message)) => (message, String::new()),
+        Err(payload) => (
+            bugi_pdk_share::panic_message(&*payload),
+            PANIC_LOCATION.take(),
+        ),
+    };
+
+    HOST.set(outer);
+    ((*host).panic)(
+        (*host).ctx,
+        message.as_ptr(),
+        message.len(),
+        location.as_ptr(),
+        location.len(),
+    );
+    std::ptr::null_mut()
+}
+
+#[export_name = "bugi_v0_low_malloc"]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    if len == 0 {
+        return NonNull::dangling().as_ptr();
+    }
+    let layout = Layout::array::<u8>(len).unwrap();
+    let ptr = unsafe { std::alloc::alloc(layout) };
+    if ptr.is_null() {
+        std::alloc::handle_alloc_error(layout)
+    } else {
+        ptr
+    }
+}
+
+/// # Safety
+/// `ptr` must be allocated by `alloc` with the same `len`
+#[export_name = "bugi_v0_low_free"]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let layout = Layout::array::<u8>(len).unwrap();
+    std::alloc::dealloc(ptr, layout)
+}
@@ -0,0 +1,116 @@
+use std::{ffi::c_void, panic::AssertUnwindSafe, sync::Mutex};
+
+use bugi_core::{
+    ffi::{DylibHost, DylibMallocFn},
+    EnvPloxy, LogLevel, LogRecord, ERROR_ABI_ID,
+};
+
+/// State of a running call, behind `DylibHost::ctx`
+pub(crate) struct CallCtx<'a> {
+    id: &'a str,
+    symbol: &'a str,
+    ploxy: EnvPloxy,
+    malloc: DylibMallocFn,
+    /// message and location reported through `DylibHost::panic`
+    panic: Mutex<Option<(String, String)>>,
+}
+
+impl<'a> CallCtx<'a> {
+    pub fn new(id: &'a str, symbol: &'a str, ploxy: EnvPloxy, malloc: DylibMallocFn) -> Self {
+        Self {
+            id,
+            symbol,
+            ploxy,
+            malloc,
+            panic: Mutex::new(None),
+        }
+    }
+
+    /// Host functions bound to this call. They must not outlive it.
+    pub fn host(&self) -> DylibHost {
+        DylibHost {
+            ctx: self as *const Self as *const c_void,
+            call_univ,
+            log,
+            panic,
+        }
+    }
+
+    pub fn take_panic(&self) -> Option<(String, String)> {
+        self.panic.lock().unwrap().take()
+    }
+}
+
+/// # Safety
+/// `ptr` must point to `len` readable bytes
+unsafe fn read_str(ptr: *const u8, len: usize) -> String {
+    String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len)).into_owned()
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn call_univ(
+    ctx: *const c_void,
+    id_ptr: *const u8,
+    id_len: usize,
+    name_ptr: *const u8,
+    name_len: usize,
+    arg_ptr: *const u8,
+    arg_len: usize,
+    abi: u64,
+    out_abi: *mut u64,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let ctx = &*(ctx as *const CallCtx);
+    let (id, name) = (read_str(id_ptr, id_len), read_str(name_ptr, name_len));
+    let arg = std::slice::from_raw_parts(arg_ptr, arg_len);
+
+    // a panic must not unwind into the plugin
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        ctx.ploxy.call_univ_raw(&id, &name, arg, abi)
+    }));
+    let (abi, res) = match result {
+        Ok(Ok(res)) => (abi, res),
+        Ok(Err(err)) => (
+            ERROR_ABI_ID,
+            format!("emit error during running function({id}:{name}): {err}").into_bytes(),
+        ),
+        Err(_) => (
+            ERROR_ABI_ID,
+            format!("host panicked during running function({id}:{name})").into_bytes(),
+        ),
+    };
+
+    let ptr = (ctx.malloc)(res.len());
+    std::ptr::copy_nonoverlapping(res.as_ptr(), ptr, res.len());
+    *out_abi = abi;
+    *out_len = res.len();
+    ptr
+}
+
+unsafe extern "C" fn log(ctx: *const c_void, level: u32, msg_ptr: *const u8, msg_len: usize) {
+    let ctx = &*(ctx as *const CallCtx);
+    let Some(level) = LogLevel::from_u32(level) else {
+        return;
+    };
+    let record = LogRecord {
+        plugin_id: ctx.id.to_string(),
+        symbol: ctx.symbol.to_string(),
+        level,
+        message: read_str(msg_ptr, msg_len),
+    };
+    let _ = std::panic::catch_unwind(AssertUnwindSafe(|| ctx.ploxy.log(record)));
+}
+
+unsafe extern "C" fn panic(
+    ctx: *const c_void,
+    msg_ptr: *const u8,
+    msg_len: usize,
+    loc_ptr: *const u8,
+    loc_len: usize,
+) {
+    let ctx = &*(ctx as *const CallCtx);
+    let report = (read_str(msg_ptr, msg_len), read_str(loc_ptr, loc_len));
+    if let Ok(mut panic) = ctx.panic.lock() {
+        *panic = Some(report);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{collections::HashMap, path::Path};
+
+use bugi_core::{
+    ffi::{
+        DylibFreeFn, DylibMallocFn, DylibPlugIdFn, DylibPluginFn, DYLIB_LOW_FREE, DYLIB_LOW_MALLOC,
+        DYLIB_PLUGIN_FUNC, DYLIB_PLUG_ID,
+    },
+    BugiError, EnvPloxy, PluginSymbol, PluginSystem,
+};
+use host::CallCtx;
+use object::Object;
+
+mod host;
+
+/// A native plugin loaded from a dynamic library.
+/// Plugin functions run in the host process, without any sandbox or limit.
+pub struct DylibPlugin {
+    str_id: String,
+    malloc: DylibMallocFn,
+    free: DylibFreeFn,
+    funcs: HashMap<String, DylibPluginFn>,
+    /// keeps the functions above valid, so it must be dropped last
+    _library: libloading::Library,
+}
+
+/// Exported symbols of a dynamic library
+fn exported_symbols(bin: &[u8]) -> anyhow::Result<Vec<String>> {
+    let file = object::File::parse(bin)?;
+    let macho = file.format() == object::BinaryFormat::MachO;
+    Ok(file
+        .exports()?
+        .iter()
+        .filter_map(|export| std::str::from_utf8(export.name()).ok())
+        // Mach-O prefixes symbols with `_`
+        .map(|name| match macho {
+            true => name.strip_prefix('_').unwrap_or(name).to_string(),
+            false => name.to_string(),
+        })
+        .collect())
+}
+
+impl DylibPlugin {
+    /// Load a native plugin.
+    ///
+    /// # Safety
+    /// The library's initialization code runs in this process, and its exports are trusted
+    /// to follow the signatures of `bugi_core::ffi`.
+    pub unsafe fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let symbols = exported_symbols(&std::fs::read(path)?)?;
+        let library = libloading::Library::new(path)?;
+
+        let get = |name: &str| {
+            library
+                .get::<unsafe extern "C" fn()>(name.as_bytes())
+                .map(|symbol| *symbol)
+                .map_err(|err| anyhow::anyhow!("`{name}` is not found: {err}"))
+        };
+
+        let plugin_id: DylibPlugIdFn = std::mem::transmute(get(DYLIB_PLUG_ID)?);
+        let malloc: DylibMallocFn = std::mem::transmute(get(DYLIB_LOW_MALLOC)?);
+        let free: DylibFreeFn = std::mem::transmute(get(DYLIB_LOW_FREE)?);
+
+        let mut funcs = HashMap::new();
+        for name in &symbols {
+            if let Some(symbol) = name.strip_prefix(DYLIB_PLUGIN_FUNC) {
+                let func: DylibPluginFn = std::mem::transmute(get(name)?);
+                funcs.insert(symbol.to_string(), func);
+            }
+        }
+
+        let mut id_len = 0;
+        let id_ptr = plugin_id(&mut id_len);
+        let str_id = String::from_utf8(std::slice::from_raw_parts(id_ptr, id_len).to_vec())
+            .map_err(|_| anyhow::anyhow!("`{DYLIB_PLUG_ID}` is not UTF-8"))?;
+
+        Ok(Self {
+            str_id,
+            malloc,
+            free,
+            funcs,
+            _library: library,
+        })
+    }
+
+    /// The plugin ID returned by `bugi_v0_plugin_id`
+    pub fn plugin_id(&self) -> &str {
+        &self.str_id
+    }
+}
+
+impl PluginSystem for DylibPlugin {
+    fn str_id(&self) -> String {
+        self.str_id.clone()
+    }
+
+    fn raw_call(
+        &self,
+        symbol: &str,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        let func = self
+            .funcs
+            .get(symbol)
+            .ok_or(BugiError::PluginCallError(format!(
+                "Symbol is not found: {}",
+                symbol
+            )))?;
+
+        let ctx = CallCtx::new(&self.str_id, symbol, ploxy, self.malloc);
+        let host = ctx.host();
+
+        let mut out_len = 0;
+        let out = unsafe {
+            let arg_ptr = (self.malloc)(param.len());
+            std::ptr::copy_nonoverlapping(param.as_ptr(), arg_ptr, param.len());
+            func(&host, arg_ptr, param.len(), abi, &mut out_len)
+        };
+
+        if out.is_null() {
+            return Err(match ctx.take_panic() {
+                Some((message, location)) => BugiError::PluginPanicked {
+                    id: self.str_id.clone(),
+                    symbol: symbol.to_string(),
+                    message,
+                    location,
+                },
+                None => {
+                    BugiError::PluginCallError(format!("`{symbol}` returned null without a reason"))
+                }
+            });
+        }
+
+        let res = unsafe {
+            let res = std::slice::from_raw_parts(out, out_len).to_vec();
+            (self.free)(out, out_len);
+            res
+        };
+        Ok(res)
+    }
+
+    fn symbols(&self) -> Vec<PluginSymbol> {
+        let mut symbols = self
+            .funcs
+            .keys()
+            .map(|name| PluginSymbol {
+                name: name.clone(),
+                abi: None,
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        symbols
+    }
+}
@@ -1,15 +1,24 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future};
 
-use bugi_core::{BugiError, EnvPloxy, PluginSystem};
+use bugi_core::{BoxFuture, BugiError, EnvPloxy, PluginSymbol, PluginSystem};
 use bugi_core::{ParamListFrom, SerializeTag, ToByte};
 
 pub(crate) type HostPluginFuncRaw =
     Box<dyn (Fn(&[u8], EnvPloxy) -> Result<Vec<u8>, BugiError>) + Send + Sync>;
 
+pub(crate) type HostPluginFuncAsyncRaw =
+    Box<dyn (Fn(&[u8], EnvPloxy) -> BoxFuture<'static, Result<Vec<u8>, BugiError>>) + Send + Sync>;
+
+pub(crate) enum HostPluginFunc {
+    Sync(HostPluginFuncRaw),
+    /// run with `block_on` by synchronous calls
+    Async(HostPluginFuncAsyncRaw),
+}
+
 #[derive(Default)]
 pub struct HostPlugin {
     name: String,
-    funcs: HashMap<String, (u64, HostPluginFuncRaw)>,
+    funcs: HashMap<String, (u64, HostPluginFunc)>,
 }
 
 impl HostPlugin {
@@ -29,27 +38,47 @@ impl HostPlugin {
             symbol.to_string(),
             (
                 SType::get_abi_id(),
-                Box::new(move |arg, ploxy| {
+                HostPluginFunc::Sync(Box::new(move |arg, ploxy| {
                     let arg = Param::from_byte(arg).map_err(BugiError::CannotSerialize)?;
                     let result = func(arg, ploxy);
                     result.to_byte().map_err(BugiError::CannotSerialize)
-                }),
+                })),
             ),
         );
     }
-}
 
-impl PluginSystem for HostPlugin {
-    fn str_id(&self) -> String {
-        self.name.clone()
-    }
-    fn raw_call(
-        &self,
+    /// Add a function returning a future. Async calls await it.
+    /// Synchronous calls drive it with `bugi_core::block_on`, so they must not be made
+    /// from inside an async executor, and the future must not need a runtime's reactor
+    /// or timers (e.g. tokio I/O or sleep) when it may be called synchronously.
+    pub fn host_func_async<
+        SType: SerializeTag,
+        Param: ParamListFrom<SType>,
+        Result: ToByte<SType>,
+        Fut: Future<Output = Result> + Send + 'static,
+    >(
+        &mut self,
         symbol: &str,
-        param: &[u8],
-        abi: u64,
-        ploxy: EnvPloxy,
-    ) -> Result<Vec<u8>, BugiError> {
+        func: impl Fn(Param, EnvPloxy) -> Fut + 'static + Send + Sync,
+    ) {
+        self.funcs.insert(
+            symbol.to_string(),
+            (
+                SType::get_abi_id(),
+                HostPluginFunc::Async(Box::new(move |arg, ploxy| {
+                    let call = Param::from_byte(arg)
+                        .map_err(BugiError::CannotSerialize)
+                        .map(|arg| func(arg, ploxy));
+                    Box::pin(async move {
+                        let result = call?.await;
+                        result.to_byte().map_err(BugiError::CannotSerialize)
+                    })
+                })),
+            ),
+        );
+    }
+
+    fn func(&self, symbol: &str, abi: u64) -> std::result::Result<&HostPluginFunc, BugiError> {
         let func = self
             .funcs
             .get(symbol)
@@ -62,9 +91,52 @@ impl PluginSystem for HostPlugin {
             return Err(BugiError::PluginAbiError(func.0));
         }
 
-        match func.1(param, ploxy) {
-            Ok(result) => Ok(result),
-            Err(e) => Err(e),
+        Ok(&func.1)
+    }
+}
+
+impl PluginSystem for HostPlugin {
+    fn str_id(&self) -> String {
+        self.name.clone()
+    }
+    fn raw_call(
+        &self,
+        symbol: &str,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        match self.func(symbol, abi)? {
+            HostPluginFunc::Sync(func) => func(param, ploxy),
+            HostPluginFunc::Async(func) => bugi_core::block_on(func(param, ploxy)),
         }
     }
+
+    fn raw_call_async<'a>(
+        &'a self,
+        symbol: &'a str,
+        param: &'a [u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> BoxFuture<'a, Result<Vec<u8>, BugiError>> {
+        Box::pin(async move {
+            match self.func(symbol, abi)? {
+                HostPluginFunc::Sync(func) => func(param, ploxy),
+                HostPluginFunc::Async(func) => func(param, ploxy).await,
+            }
+        })
+    }
+
+    fn symbols(&self) -> Vec<PluginSymbol> {
+        let mut symbols = self
+            .funcs
+            .iter()
+            .map(|(name, (abi, _))| PluginSymbol {
+                name: name.clone(),
+                abi: Some(*abi),
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        symbols
+    }
 }
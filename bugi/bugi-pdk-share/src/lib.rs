@@ -0,0 +1,76 @@
+//! Code shared by the plugin development kits.
+
+use std::any::Any;
+use std::sync::OnceLock;
+
+pub use bugi_share::LogLevel;
+
+/// How the PDK of the plugin writes a log record to the host
+static LOG_SINK: OnceLock<fn(LogLevel, &str)> = OnceLock::new();
+
+/// Set where the log macros write. The PDK does this before running a plugin function.
+pub fn set_log_sink(sink: fn(LogLevel, &str)) {
+    let _ = LOG_SINK.set(sink);
+}
+
+/// Write a log record through the sink set by the PDK.
+/// Records written before the PDK sets it are dropped.
+pub fn write_log(level: LogLevel, message: &str) {
+    if let Some(sink) = LOG_SINK.get() {
+        sink(level, message)
+    }
+}
+
+/// `format!` a message and write it to the host's log sink
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::write_log($level, &format!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::LogLevel::Error, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::LogLevel::Warn, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::LogLevel::Info, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::LogLevel::Debug, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::LogLevel::Trace, $($arg)+)
+    };
+}
+
+/// Message of a panic payload, as `panic!` formats it
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    }
+}
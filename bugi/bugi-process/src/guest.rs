@@ -0,0 +1,76 @@
+use std::{
+    io::{Stdin, Stdout},
+    sync::Arc,
+};
+
+use bugi_core::{Cacher, EnvPloxy, LogRecord, PluginSystem};
+
+use crate::{
+    peer::{Handler, Peer},
+    wire::Message,
+};
+
+/// Serve `plugin` to the host through stdin and stdout, until the host closes stdin.
+///
+/// stdout carries the frames, so the plugin must not print to it. stderr is free to use.
+/// Functions must call back into the universe from the thread they run on.
+pub fn serve_stdio(plugin: impl PluginSystem + 'static) -> std::io::Result<()> {
+    let guest = Guest(Arc::new(GuestInner {
+        plugin: Box::new(plugin),
+        peer: Peer::new(std::io::stdin(), std::io::stdout()),
+        cacher: Cacher::new(),
+    }));
+
+    guest.0.peer.send(&Message::Hello {
+        id: guest.0.plugin.str_id(),
+        symbols: guest.0.plugin.symbols(),
+    })?;
+
+    guest.0.peer.serve(&guest)
+}
+
+#[derive(Clone)]
+struct Guest(Arc<GuestInner>);
+
+struct GuestInner {
+    plugin: Box<dyn PluginSystem>,
+    peer: Peer<Stdin, Stdout>,
+    /// the cache lives in the plugin process
+    cacher: Cacher,
+}
+
+impl Guest {
+    fn ploxy(&self) -> EnvPloxy {
+        let (call, log) = (self.clone(), self.clone());
+        EnvPloxy::new(
+            Some(&self.0.cacher),
+            Box::new(move |id, symbol, arg, abi, _| {
+                call.0
+                    .peer
+                    .call(id, symbol, abi, arg, &call)
+                    .map_err(|err| bugi_core::BugiError::PluginCallError(err.to_string()))?
+                    .map_err(bugi_core::BugiError::PluginCallError)
+            }),
+            0,
+            Some(Arc::new(move |record: LogRecord| {
+                let _ = log.0.peer.send(&Message::Log {
+                    parent: None,
+                    level: record.level as u32,
+                    message: record.message,
+                });
+            })),
+        )
+    }
+}
+
+impl Handler for Guest {
+    fn call(&self, _id: &str, name: &str, abi: u64, detail: &[u8]) -> Result<Vec<u8>, String> {
+        self.0
+            .plugin
+            .raw_call(name, detail, abi, self.ploxy())
+            .map_err(|err| err.to_string())
+    }
+
+    /// the host doesn't send logs
+    fn log(&self, _level: u32, _message: String) {}
+}
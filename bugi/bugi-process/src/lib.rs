@@ -0,0 +1,196 @@
+use std::{
+    ffi::OsString,
+    panic::AssertUnwindSafe,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use bugi_core::{BugiError, EnvPloxy, LogLevel, LogRecord, PluginSymbol, PluginSystem};
+use peer::{Handler, Peer};
+
+mod guest;
+mod peer;
+pub mod wire;
+
+pub use guest::serve_stdio;
+
+/// How long a broken connection waits for the child to exit
+const EXIT_POLLS: usize = 20;
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A plugin running in a child process, talking frames of `wire` over its stdin and stdout.
+/// A crash of the child fails the running call with `BugiError::PluginExited`,
+/// and `reset` starts a new child.
+pub struct ProcessPlugin {
+    program: OsString,
+    args: Vec<OsString>,
+    str_id: String,
+    symbols: Vec<PluginSymbol>,
+    process: RwLock<Arc<Process>>,
+}
+
+struct Process {
+    child: Mutex<Child>,
+    peer: Peer<ChildStdout, ChildStdin>,
+}
+
+impl Process {
+    fn spawn(program: &OsString, args: &[OsString]) -> anyhow::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (stdin, stdout) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+        Ok(Self {
+            child: Mutex::new(child),
+            peer: Peer::new(stdout, stdin),
+        })
+    }
+
+    /// Exit status, if the child has exited.
+    /// The pipes close a bit before the child becomes waitable, so this waits briefly.
+    fn exited(&self) -> Option<String> {
+        let mut child = self.child.lock().unwrap();
+        for _ in 0..EXIT_POLLS {
+            if let Some(status) = child.try_wait().ok()? {
+                return Some(status.to_string());
+            }
+            std::thread::sleep(EXIT_POLL_INTERVAL);
+        }
+        None
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let child = self.child.get_mut().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+impl ProcessPlugin {
+    /// Start `program` and read its plugin ID and functions
+    pub fn spawn(
+        program: impl Into<OsString>,
+        args: impl IntoIterator<Item = impl Into<OsString>>,
+    ) -> anyhow::Result<Self> {
+        let program = program.into();
+        let args = args.into_iter().map(Into::into).collect::<Vec<_>>();
+
+        let process = Process::spawn(&program, &args)?;
+        let (str_id, mut symbols) = process
+            .peer
+            .recv_hello()
+            .map_err(|err| anyhow::anyhow!("plugin process didn't say hello: {err}"))?;
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Self {
+            program,
+            args,
+            str_id,
+            symbols,
+            process: RwLock::new(Arc::new(process)),
+        })
+    }
+
+    /// The plugin ID sent by the child
+    pub fn plugin_id(&self) -> &str {
+        &self.str_id
+    }
+}
+
+/// Serves the callbacks of the child during a call
+struct CallHandler<'a> {
+    id: &'a str,
+    symbol: &'a str,
+    ploxy: EnvPloxy,
+}
+
+impl Handler for CallHandler<'_> {
+    fn call(&self, id: &str, name: &str, abi: u64, detail: &[u8]) -> Result<Vec<u8>, String> {
+        // a panic must not leave the connection half-way
+        match std::panic::catch_unwind(AssertUnwindSafe(|| {
+            self.ploxy.call_univ_raw(id, name, detail, abi)
+        })) {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(err)) => Err(format!(
+                "emit error during running function({id}:{name}): {err}"
+            )),
+            Err(_) => Err(format!(
+                "host panicked during running function({id}:{name})"
+            )),
+        }
+    }
+
+    fn log(&self, level: u32, message: String) {
+        let Some(level) = LogLevel::from_u32(level) else {
+            return;
+        };
+        self.ploxy.log(LogRecord {
+            plugin_id: self.id.to_string(),
+            symbol: self.symbol.to_string(),
+            level,
+            message,
+        });
+    }
+}
+
+impl PluginSystem for ProcessPlugin {
+    fn str_id(&self) -> String {
+        self.str_id.clone()
+    }
+
+    fn raw_call(
+        &self,
+        symbol: &str,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        let process = self.process.read().unwrap().clone();
+        let handler = CallHandler {
+            id: &self.str_id,
+            symbol,
+            ploxy,
+        };
+
+        match process
+            .peer
+            .call(&self.str_id, symbol, abi, param, &handler)
+        {
+            Ok(res) => res.map_err(BugiError::PluginCallError),
+            Err(err) => Err(match process.exited() {
+                Some(status) => BugiError::PluginExited {
+                    id: self.str_id.clone(),
+                    symbol: symbol.to_string(),
+                    status,
+                },
+                None => {
+                    BugiError::PluginCallError(format!("cannot talk to the plugin process: {err}"))
+                }
+            }),
+        }
+    }
+
+    fn symbols(&self) -> Vec<PluginSymbol> {
+        self.symbols.clone()
+    }
+
+    /// Replace the child with a new one. The old child is kept if the new one fails to start.
+    fn reset(&self) -> Result<(), BugiError> {
+        let process = Process::spawn(&self.program, &self.args).map_err(|err| {
+            BugiError::PluginLoadError(format!("cannot restart the plugin process: {err}"))
+        })?;
+        let (id, _) = process.peer.recv_hello().map_err(|err| {
+            BugiError::PluginLoadError(format!("the restarted plugin process failed: {err}"))
+        })?;
+        if id != self.str_id {
+            return Err(BugiError::PluginIdMismatch(self.str_id.clone(), id));
+        }
+        *self.process.write().unwrap() = Arc::new(process);
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Condvar, Mutex,
+    },
+    thread::ThreadId,
+};
+
+use bugi_core::{PluginSymbol, ERROR_ABI_ID};
+
+use crate::wire::{read_message, write_message, Message};
+
+/// Serves the calls coming from the other side
+pub(crate) trait Handler {
+    /// Run a function. An error is sent back as the message of an `ERROR_ABI_ID` result.
+    fn call(&self, id: &str, name: &str, abi: u64, detail: &[u8]) -> Result<Vec<u8>, String>;
+
+    fn log(&self, level: u32, message: String);
+}
+
+/// One end of a connection.
+///
+/// Calls are strictly nested: while waiting for its result, a call serves the calls of
+/// the other side, which may call back again on the same thread.
+/// Calls from other threads wait until the outermost call returns.
+pub(crate) struct Peer<R, W> {
+    reader: Mutex<R>,
+    writer: Mutex<W>,
+    next_req: AtomicU64,
+    /// thread using the connection, with its depth of calls
+    owner: Mutex<Option<(ThreadId, usize)>>,
+    released: Condvar,
+}
+
+/// Ownership of the connection, released on drop
+struct Turn<'a, R, W>(&'a Peer<R, W>);
+
+impl<R, W> Drop for Turn<'_, R, W> {
+    fn drop(&mut self) {
+        let mut owner = self.0.owner.lock().unwrap();
+        if let Some((_, depth)) = owner.as_mut() {
+            *depth -= 1;
+            if *depth == 0 {
+                *owner = None;
+                self.0.released.notify_one();
+            }
+        }
+    }
+}
+
+fn unexpected(message: &Message) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("unexpected message: {message:?}"),
+    )
+}
+
+impl<R: Read, W: Write> Peer<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+            next_req: AtomicU64::new(0),
+            owner: Mutex::new(None),
+            released: Condvar::new(),
+        }
+    }
+
+    fn turn(&self) -> Turn<'_, R, W> {
+        let me = std::thread::current().id();
+        let mut owner = self.owner.lock().unwrap();
+        loop {
+            match owner.as_mut() {
+                None => {
+                    *owner = Some((me, 1));
+                    break;
+                }
+                Some((thread, depth)) if *thread == me => {
+                    *depth += 1;
+                    break;
+                }
+                Some(_) => owner = self.released.wait(owner).unwrap(),
+            }
+        }
+        Turn(self)
+    }
+
+    pub fn send(&self, message: &Message) -> std::io::Result<()> {
+        write_message(&mut *self.writer.lock().unwrap(), message)
+    }
+
+    /// Read a message. The end of the stream is an `UnexpectedEof` error.
+    fn recv(&self) -> std::io::Result<Message> {
+        read_message(&mut *self.reader.lock().unwrap())?.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed")
+        })
+    }
+
+    /// Read the first message, which must be `Hello`
+    pub fn recv_hello(&self) -> std::io::Result<(String, Vec<PluginSymbol>)> {
+        let _turn = self.turn();
+        match self.recv()? {
+            Message::Hello { id, symbols } => Ok((id, symbols)),
+            message => Err(unexpected(&message)),
+        }
+    }
+
+    /// Call a function of the other side.
+    /// The outer `Result` fails if the connection is broken, the inner one if the function failed.
+    pub fn call(
+        &self,
+        id: &str,
+        name: &str,
+        abi: u64,
+        detail: &[u8],
+        handler: &dyn Handler,
+    ) -> std::io::Result<Result<Vec<u8>, String>> {
+        let _turn = self.turn();
+        let req = self.next_req.fetch_add(1, Ordering::Relaxed);
+        self.send(&Message::Call {
+            req,
+            parent: None,
+            id: id.to_string(),
+            name: name.to_string(),
+            abi,
+            detail: detail.to_vec(),
+        })?;
+
+        loop {
+            match self.recv()? {
+                Message::Result {
+                    req: res_req,
+                    abi,
+                    detail,
+                } if res_req == req => {
+                    return Ok(match abi {
+                        ERROR_ABI_ID => Err(String::from_utf8_lossy(&detail).into_owned()),
+                        _ => Ok(detail),
+                    });
+                }
+                message => self.handle(message, handler)?,
+            }
+        }
+    }
+
+    /// Serve the calls of the other side until it closes the connection
+    pub fn serve(&self, handler: &dyn Handler) -> std::io::Result<()> {
+        let _turn = self.turn();
+        loop {
+            // the reader must be unlocked while handling, a handler may call back
+            let message = read_message(&mut *self.reader.lock().unwrap())?;
+            match message {
+                Some(message) => self.handle(message, handler)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn handle(&self, message: Message, handler: &dyn Handler) -> std::io::Result<()> {
+        match message {
+            Message::Call {
+                req,
+                parent: _,
+                id,
+                name,
+                abi,
+                detail,
+            } => {
+                let (abi, detail) = match handler.call(&id, &name, abi, &detail) {
+                    Ok(detail) => (abi, detail),
+                    Err(message) => (ERROR_ABI_ID, message.into_bytes()),
+                };
+                self.send(&Message::Result { req, abi, detail })
+            }
+            Message::Log {
+                parent: _,
+                level,
+                message,
+            } => {
+                handler.log(level, message);
+                Ok(())
+            }
+            message => Err(unexpected(&message)),
+        }
+    }
+}
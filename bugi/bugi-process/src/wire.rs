@@ -0,0 +1,260 @@
+//! Messages exchanged with an out-of-process plugin.
+//!
+//! A frame is a big-endian `u32` byte length followed by a messagepack map.
+//! `Call` has the shape of the `call_univ` ARG map and `Result` the shape of its RESULT map,
+//! both with a `req` number pairing them.
+//! `parent` is the `req` of the call of the other side a message is sent from, if any.
+//! Only `bugi-remote` sets it, to route messages of concurrent calls over one connection.
+//! A process plugin runs one call at a time, so it always sends `None` and ignores the field.
+
+use std::io::{Read, Write};
+
+use bugi_core::PluginSymbol;
+use rmpv::Value;
+
+/// Largest frame accepted, so that a broken peer can't make us allocate without bound
+pub const MAX_FRAME_LEN: u32 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// First message of the plugin: its ID and functions
+    Hello {
+        id: String,
+        symbols: Vec<PluginSymbol>,
+    },
+    /// First message of a server: every plugin of its universe
+    Plugins {
+        plugins: Vec<(String, Vec<PluginSymbol>)>,
+    },
+    /// Call a function on the other side
+    Call {
+        req: u64,
+        parent: Option<u64>,
+        id: String,
+        name: String,
+        abi: u64,
+        detail: Vec<u8>,
+    },
+    /// Result of the `Call` with the same `req`.
+    /// `abi` is `ERROR_ABI_ID` on failure and `detail` the UTF-8 error message.
+    Result { req: u64, abi: u64, detail: Vec<u8> },
+    /// Log record of the running call
+    Log {
+        parent: Option<u64>,
+        level: u32,
+        message: String,
+    },
+}
+
+fn key(name: &str) -> Value {
+    Value::String(name.into())
+}
+
+fn optional(value: Option<u64>) -> Value {
+    value.map_or(Value::Nil, |value| value.into())
+}
+
+fn symbols_to_value(symbols: &[PluginSymbol]) -> Value {
+    Value::Array(
+        symbols
+            .iter()
+            .map(|symbol| {
+                Value::Map(vec![
+                    (key("name"), Value::String(symbol.name.as_str().into())),
+                    (key("abi"), optional(symbol.abi)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn symbols_from_value(value: &Value) -> Result<Vec<PluginSymbol>, String> {
+    value
+        .as_array()
+        .ok_or("`symbols` is not array")?
+        .iter()
+        .map(|symbol| {
+            let name = symbol["name"].as_str().ok_or("symbol has no `name`")?;
+            Ok(PluginSymbol {
+                name: name.to_string(),
+                abi: symbol["abi"].as_u64(),
+            })
+        })
+        .collect()
+}
+
+impl Message {
+    fn to_value(&self) -> Value {
+        let (kind, mut fields) = match self {
+            Message::Hello { id, symbols } => (
+                "hello",
+                vec![
+                    (key("id"), Value::String(id.as_str().into())),
+                    (key("symbols"), symbols_to_value(symbols)),
+                ],
+            ),
+            Message::Plugins { plugins } => (
+                "plugins",
+                vec![(
+                    key("plugins"),
+                    Value::Array(
+                        plugins
+                            .iter()
+                            .map(|(id, symbols)| {
+                                Value::Map(vec![
+                                    (key("id"), Value::String(id.as_str().into())),
+                                    (key("symbols"), symbols_to_value(symbols)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                )],
+            ),
+            Message::Call {
+                req,
+                parent,
+                id,
+                name,
+                abi,
+                detail,
+            } => (
+                "call",
+                vec![
+                    (key("req"), (*req).into()),
+                    (key("parent"), optional(*parent)),
+                    (key("id"), Value::String(id.as_str().into())),
+                    (key("name"), Value::String(name.as_str().into())),
+                    (key("abi"), (*abi).into()),
+                    (key("detail"), Value::Binary(detail.clone())),
+                ],
+            ),
+            Message::Result { req, abi, detail } => (
+                "result",
+                vec![
+                    (key("req"), (*req).into()),
+                    (key("abi"), (*abi).into()),
+                    (key("detail"), Value::Binary(detail.clone())),
+                ],
+            ),
+            Message::Log {
+                parent,
+                level,
+                message,
+            } => (
+                "log",
+                vec![
+                    (key("parent"), optional(*parent)),
+                    (key("level"), (*level).into()),
+                    (key("message"), Value::String(message.as_str().into())),
+                ],
+            ),
+        };
+        fields.insert(0, (key("type"), Value::String(kind.into())));
+        Value::Map(fields)
+    }
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        let Value::Map(map) = value else {
+            return Err("message is not map".to_string());
+        };
+        let get = |name: &str| {
+            map.iter()
+                .find(|(k, _)| k.as_str() == Some(name))
+                .map(|(_, v)| v)
+                .ok_or_else(|| format!("message is not satisfied: `{name}`"))
+        };
+        let string = |name: &str| {
+            get(name)?
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| format!("`{name}` is not string"))
+        };
+        let uint = |name: &str| {
+            get(name)?
+                .as_u64()
+                .ok_or_else(|| format!("`{name}` is not unsigned integer"))
+        };
+        // a missing or nil value is `None`
+        let parent = || {
+            map.iter()
+                .find(|(k, _)| k.as_str() == Some("parent"))
+                .and_then(|(_, v)| v.as_u64())
+        };
+        let binary = |name: &str| {
+            get(name)?
+                .as_slice()
+                .map(|b| b.to_vec())
+                .ok_or_else(|| format!("`{name}` is not binary"))
+        };
+
+        match string("type")?.as_str() {
+            "hello" => Ok(Message::Hello {
+                id: string("id")?,
+                symbols: symbols_from_value(get("symbols")?)?,
+            }),
+            "plugins" => Ok(Message::Plugins {
+                plugins: get("plugins")?
+                    .as_array()
+                    .ok_or("`plugins` is not array")?
+                    .iter()
+                    .map(|plugin| {
+                        let id = plugin["id"].as_str().ok_or("plugin has no `id`")?;
+                        Ok((id.to_string(), symbols_from_value(&plugin["symbols"])?))
+                    })
+                    .collect::<Result<_, String>>()?,
+            }),
+            "call" => Ok(Message::Call {
+                req: uint("req")?,
+                parent: parent(),
+                id: string("id")?,
+                name: string("name")?,
+                abi: uint("abi")?,
+                detail: binary("detail")?,
+            }),
+            "result" => Ok(Message::Result {
+                req: uint("req")?,
+                abi: uint("abi")?,
+                detail: binary("detail")?,
+            }),
+            "log" => Ok(Message::Log {
+                parent: parent(),
+                level: uint("level")? as u32,
+                message: string("message")?,
+            }),
+            kind => Err(format!("unknown message type: {kind}")),
+        }
+    }
+}
+
+/// Write a frame
+pub fn write_message(w: &mut impl Write, message: &Message) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    rmpv::encode::write_value(&mut body, &message.to_value())?;
+    w.write_all(&(body.len() as u32).to_be_bytes())?;
+    w.write_all(&body)?;
+    w.flush()
+}
+
+/// Read a frame. Returns `None` at the end of the stream.
+pub fn read_message(r: &mut impl Read) -> std::io::Result<Option<Message>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(invalid(format!("frame is too large: {len} bytes")));
+    }
+    let mut body = vec![0; len as usize];
+    r.read_exact(&mut body)?;
+
+    let value = rmpv::decode::read_value(&mut &body[..]).map_err(|err| invalid(err.to_string()))?;
+    Message::from_value(value).map(Some).map_err(invalid)
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,292 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+};
+
+use bugi_core::ERROR_ABI_ID;
+use bugi_process::wire::{read_message, write_message, Message};
+
+/// A byte stream a connection runs on
+pub trait Stream: Read + Write + Send + Sync + Sized + 'static {
+    fn try_clone(&self) -> std::io::Result<Self>;
+
+    /// Close both directions, which wakes up a blocked reader
+    fn shutdown(&self) -> std::io::Result<()>;
+}
+
+impl Stream for std::net::TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        std::net::TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        std::net::TcpStream::shutdown(self, std::net::Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        std::os::unix::net::UnixStream::shutdown(self, std::net::Shutdown::Both)
+    }
+}
+
+/// Serves the calls and logs the other side sends while one of our calls runs
+pub trait Handler {
+    /// Run a function. An error is sent back as the message of an `ERROR_ABI_ID` result.
+    fn call(&self, id: &str, name: &str, abi: u64, detail: &[u8]) -> Result<Vec<u8>, String>;
+
+    fn log(&self, level: u32, message: String);
+}
+
+/// Serves the calls the other side makes outside of any of our calls.
+/// Each of them runs on a new thread.
+pub type RootHandler =
+    dyn Fn(&Arc<Connection>, &str, &str, u64, &[u8]) -> Result<Vec<u8>, String> + Send + Sync;
+
+/// What the reader thread passes to a waiting call
+enum Incoming {
+    Result(Result<Vec<u8>, String>),
+    Call {
+        req: u64,
+        id: String,
+        name: String,
+        abi: u64,
+        detail: Vec<u8>,
+    },
+    Log(u32, String),
+}
+
+thread_local! {
+    /// Requests of the other side served by this thread, innermost last, with their connection
+    static SERVING: RefCell<Vec<(usize, u64)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A connection multiplexing calls in both directions.
+///
+/// Any number of threads may call at once. A call of the other side made while
+/// one of our calls runs is served by the thread waiting for that call.
+pub struct Connection {
+    writer: Mutex<Box<dyn Write + Send>>,
+    shutdown: Box<dyn Fn() + Send + Sync>,
+    next_req: AtomicU64,
+    /// waiting calls; `None` once the connection is closed
+    pending: Mutex<Option<HashMap<u64, Sender<Incoming>>>>,
+}
+
+impl Connection {
+    /// Start a connection on `stream`, whose first message was already exchanged.
+    /// It stays open until either side closes it.
+    pub fn new<S: Stream>(stream: S, root: Arc<RootHandler>) -> std::io::Result<Arc<Self>> {
+        let reader = stream.try_clone()?;
+        let shutdown = stream.try_clone()?;
+        let conn = Arc::new(Self {
+            writer: Mutex::new(Box::new(stream)),
+            shutdown: Box::new(move || {
+                let _ = shutdown.shutdown();
+            }),
+            next_req: AtomicU64::new(0),
+            pending: Mutex::new(Some(HashMap::new())),
+        });
+
+        let reader_conn = conn.clone();
+        std::thread::spawn(move || {
+            let mut reader = reader;
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                reader_conn.dispatch(message, &root);
+            }
+            // fail every waiting call
+            reader_conn.pending.lock().unwrap().take();
+        });
+
+        Ok(conn)
+    }
+
+    /// Close the connection. Waiting calls fail.
+    pub fn close(&self) {
+        (self.shutdown)();
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn send(&self, message: &Message) -> std::io::Result<()> {
+        write_message(&mut *self.writer.lock().unwrap(), message)
+    }
+
+    /// The request this thread serves for the other side, if any
+    fn parent(&self) -> Option<u64> {
+        SERVING.with(|serving| {
+            serving
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(key, _)| *key == self.key())
+                .map(|(_, req)| *req)
+        })
+    }
+
+    /// Run `f` as the server of the request `req`
+    fn serving<T>(&self, req: u64, f: impl FnOnce() -> T) -> T {
+        SERVING.with(|serving| serving.borrow_mut().push((self.key(), req)));
+        let res = f();
+        SERVING.with(|serving| serving.borrow_mut().pop());
+        res
+    }
+
+    fn dispatch(self: &Arc<Self>, message: Message, root: &Arc<RootHandler>) {
+        let route = |parent: u64, incoming: Incoming| -> Result<(), Incoming> {
+            let pending = self.pending.lock().unwrap();
+            match pending.as_ref().and_then(|pending| pending.get(&parent)) {
+                Some(sender) => sender.send(incoming).map_err(|err| err.0),
+                None => Err(incoming),
+            }
+        };
+
+        match message {
+            Message::Result { req, abi, detail } => {
+                let sender = self
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .and_then(|pending| pending.remove(&req));
+                if let Some(sender) = sender {
+                    let _ = sender.send(Incoming::Result(match abi {
+                        ERROR_ABI_ID => Err(String::from_utf8_lossy(&detail).into_owned()),
+                        _ => Ok(detail),
+                    }));
+                }
+            }
+            Message::Call {
+                req,
+                parent: Some(parent),
+                id,
+                name,
+                abi,
+                detail,
+            } => {
+                let incoming = Incoming::Call {
+                    req,
+                    id,
+                    name,
+                    abi,
+                    detail,
+                };
+                if let Err(Incoming::Call { req, .. }) = route(parent, incoming) {
+                    let _ = self.send_result(
+                        req,
+                        abi,
+                        Err(format!("the parent call has finished: {parent}")),
+                    );
+                }
+            }
+            Message::Call {
+                req,
+                parent: None,
+                id,
+                name,
+                abi,
+                detail,
+            } => {
+                let (conn, root) = (self.clone(), root.clone());
+                std::thread::spawn(move || {
+                    let res = conn.serving(req, || root(&conn, &id, &name, abi, &detail));
+                    let _ = conn.send_result(req, abi, res);
+                });
+            }
+            Message::Log {
+                parent: Some(parent),
+                level,
+                message,
+            } => {
+                let _ = route(parent, Incoming::Log(level, message));
+            }
+            // nothing to attribute the record to
+            Message::Log { parent: None, .. } => {}
+            Message::Hello { .. } | Message::Plugins { .. } => {}
+        }
+    }
+
+    fn send_result(&self, req: u64, abi: u64, res: Result<Vec<u8>, String>) -> std::io::Result<()> {
+        let (abi, detail) = match res {
+            Ok(detail) => (abi, detail),
+            Err(message) => (ERROR_ABI_ID, message.into_bytes()),
+        };
+        self.send(&Message::Result { req, abi, detail })
+    }
+
+    /// Call a function of the other side.
+    /// The outer `Result` fails if the connection is broken, the inner one if the function failed.
+    pub fn call(
+        &self,
+        id: &str,
+        name: &str,
+        abi: u64,
+        detail: &[u8],
+        handler: &dyn Handler,
+    ) -> std::io::Result<Result<Vec<u8>, String>> {
+        let req = self.next_req.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(req, sender),
+            None => return Err(closed()),
+        };
+
+        let sent = self.send(&Message::Call {
+            req,
+            parent: self.parent(),
+            id: id.to_string(),
+            name: name.to_string(),
+            abi,
+            detail: detail.to_vec(),
+        });
+        if let Err(err) = sent {
+            if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                pending.remove(&req);
+            }
+            return Err(err);
+        }
+
+        loop {
+            match receiver.recv().map_err(|_| closed())? {
+                Incoming::Result(res) => return Ok(res),
+                Incoming::Call {
+                    req,
+                    id,
+                    name,
+                    abi,
+                    detail,
+                } => {
+                    let res = self.serving(req, || handler.call(&id, &name, abi, &detail));
+                    self.send_result(req, abi, res)?;
+                }
+                Incoming::Log(level, message) => handler.log(level, message),
+            }
+        }
+    }
+
+    /// Send a log record of the request this thread serves
+    pub fn log(&self, level: u32, message: String) -> std::io::Result<()> {
+        self.send(&Message::Log {
+            parent: self.parent(),
+            level,
+            message,
+        })
+    }
+}
+
+fn closed() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "connection closed")
+}
@@ -0,0 +1,145 @@
+use std::{net::ToSocketAddrs, sync::Arc};
+
+use bugi_core::{BugiError, EnvPloxy, LogLevel, LogRecord, PluginSymbol, PluginSystem};
+use bugi_process::wire::{read_message, write_message, Message};
+
+mod conn;
+
+pub use conn::{Connection, Handler, RootHandler, Stream};
+
+/// A connection to a `bugi-server`, sharing the plugins of its universe.
+/// It's closed when the client and all of its plugins are dropped.
+pub struct RemoteClient {
+    conn: Arc<ClientConn>,
+    plugins: Vec<(String, Vec<PluginSymbol>)>,
+}
+
+struct ClientConn(Arc<Connection>);
+
+impl Drop for ClientConn {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+impl RemoteClient {
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        Self::connect(std::net::TcpStream::connect(addr)?)
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        Self::connect(std::os::unix::net::UnixStream::connect(path)?)
+    }
+
+    /// Start a connection on `stream`, reading the plugin list the server sends first
+    pub fn connect<S: Stream>(mut stream: S) -> anyhow::Result<Self> {
+        let plugins = match read_message(&mut stream)? {
+            Some(Message::Plugins { plugins }) => plugins,
+            message => anyhow::bail!("the server didn't send its plugins: {message:?}"),
+        };
+
+        // the server only calls back from inside our calls
+        let conn = Connection::new(
+            stream,
+            Arc::new(|_: &Arc<Connection>, id: &str, name: &str, _, _: &[u8]| {
+                Err(format!("no call to run function({id}:{name}) in"))
+            }),
+        )?;
+        Ok(Self {
+            conn: Arc::new(ClientConn(conn)),
+            plugins,
+        })
+    }
+
+    /// Every plugin of the server, as of the connection
+    pub fn plugins(&self) -> Vec<RemotePlugin> {
+        self.plugins
+            .iter()
+            .map(|(str_id, symbols)| RemotePlugin {
+                str_id: str_id.clone(),
+                symbols: symbols.clone(),
+                conn: self.conn.clone(),
+            })
+            .collect()
+    }
+
+    /// The plugin of the server with the string ID `str_id`
+    pub fn plugin(&self, str_id: &str) -> Option<RemotePlugin> {
+        self.plugins()
+            .into_iter()
+            .find(|plugin| plugin.str_id == str_id)
+    }
+}
+
+/// A plugin of a `bugi-server`.
+/// Its calls into the universe, and its log records, come back to the caller's universe.
+pub struct RemotePlugin {
+    str_id: String,
+    symbols: Vec<PluginSymbol>,
+    conn: Arc<ClientConn>,
+}
+
+/// Serves the callbacks of the server during a call
+struct CallHandler<'a> {
+    id: &'a str,
+    symbol: &'a str,
+    ploxy: EnvPloxy,
+}
+
+impl Handler for CallHandler<'_> {
+    fn call(&self, id: &str, name: &str, abi: u64, detail: &[u8]) -> Result<Vec<u8>, String> {
+        self.ploxy
+            .call_univ_raw(id, name, detail, abi)
+            .map_err(|err| format!("emit error during running function({id}:{name}): {err}"))
+    }
+
+    fn log(&self, level: u32, message: String) {
+        let Some(level) = LogLevel::from_u32(level) else {
+            return;
+        };
+        self.ploxy.log(LogRecord {
+            plugin_id: self.id.to_string(),
+            symbol: self.symbol.to_string(),
+            level,
+            message,
+        });
+    }
+}
+
+impl PluginSystem for RemotePlugin {
+    fn str_id(&self) -> String {
+        self.str_id.clone()
+    }
+
+    fn raw_call(
+        &self,
+        symbol: &str,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        let handler = CallHandler {
+            id: &self.str_id,
+            symbol,
+            ploxy,
+        };
+        self.conn
+            .0
+            .call(&self.str_id, symbol, abi, param, &handler)
+            .map_err(|err| BugiError::PluginCallError(format!("remote connection error: {err}")))?
+            .map_err(BugiError::PluginCallError)
+    }
+
+    fn symbols(&self) -> Vec<PluginSymbol> {
+        self.symbols.clone()
+    }
+}
+
+/// Send the plugin list a `RemoteClient` expects first
+pub fn send_plugins(
+    stream: &mut impl Stream,
+    plugins: Vec<(String, Vec<PluginSymbol>)>,
+) -> std::io::Result<()> {
+    write_message(stream, &Message::Plugins { plugins })
+}
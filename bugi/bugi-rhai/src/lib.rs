@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use bugi_core::{
+    BugiError, EnvPloxy, ExecutionLimit, LogLevel, LogRecord, PluginSymbol, PluginSystem,
+    SerializeError, RMP_ABI_ID,
+};
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, CallFnOptions, Dynamic, Engine, EvalAltResult,
+    FnAccess, NativeCallContext, Scope, AST,
+};
+
+/// A plugin written in Rhai.
+///
+/// Every public function of the script is a plugin function taking and returning `RmpTag` data.
+/// Top-level statements run before each call, and `import` is disabled.
+///
+/// Scripts can use:
+/// - `call_univ(id, symbol)` and `call_univ(id, symbol, [args...])` to call the universe
+/// - `log(level, message)` with `"error"`, `"warn"`, `"info"`, `"debug"` or `"trace"`
+pub struct RhaiPlugin {
+    str_id: String,
+    engine: Engine,
+    ast: AST,
+}
+
+/// The running call, passed to native functions through the call tag
+#[derive(Clone)]
+struct CallTag {
+    id: String,
+    symbol: String,
+    ploxy: EnvPloxy,
+}
+
+fn call_tag(ctx: &NativeCallContext) -> Result<CallTag, Box<EvalAltResult>> {
+    ctx.tag()
+        .and_then(|tag| tag.clone().try_cast::<CallTag>())
+        .ok_or_else(|| "not in a plugin call".into())
+}
+
+fn call_univ(
+    ctx: NativeCallContext,
+    id: &str,
+    symbol: &str,
+    args: Array,
+) -> Result<Dynamic, Box<EvalAltResult>> {
+    let ploxy = call_tag(&ctx)?.ploxy;
+    // an empty list is `()` to the callee
+    let arg = match args.is_empty() {
+        true => rmp_serde::to_vec_named(&()),
+        false => rmp_serde::to_vec_named(&args),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let res = ploxy
+        .call_univ_raw(id, symbol, &arg, RMP_ABI_ID)
+        .map_err(|err| format!("emit error during running function({id}:{symbol}): {err}"))?;
+    Ok(rmp_serde::from_slice(&res).map_err(|err| err.to_string())?)
+}
+
+fn log(ctx: NativeCallContext, level: &str, message: &str) -> Result<(), Box<EvalAltResult>> {
+    let level = match level {
+        "error" => LogLevel::Error,
+        "warn" => LogLevel::Warn,
+        "info" => LogLevel::Info,
+        "debug" => LogLevel::Debug,
+        "trace" => LogLevel::Trace,
+        level => return Err(format!("unknown log level: {level}").into()),
+    };
+    let tag = call_tag(&ctx)?;
+    tag.ploxy.log(LogRecord {
+        plugin_id: tag.id,
+        symbol: tag.symbol,
+        level,
+        message: message.to_string(),
+    });
+    Ok(())
+}
+
+impl RhaiPlugin {
+    /// Compile a script
+    pub fn new(str_id: &str, script: &str) -> anyhow::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.register_fn("call_univ", call_univ);
+        engine.register_fn(
+            "call_univ",
+            |ctx: NativeCallContext, id: &str, symbol: &str| {
+                call_univ(ctx, id, symbol, Array::new())
+            },
+        );
+        engine.register_fn("log", log);
+
+        let ast = engine.compile(script)?;
+        Ok(Self {
+            str_id: str_id.to_string(),
+            engine,
+            ast,
+        })
+    }
+
+    /// Compile a script file
+    pub fn load(str_id: &str, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new(str_id, &std::fs::read_to_string(path)?)
+    }
+
+    /// Limit the operations a call may run. It's reported as `ExecutionLimit::CallFuel`.
+    pub fn set_max_operations(&mut self, operations: u64) {
+        self.engine.set_max_operations(operations);
+    }
+}
+
+impl PluginSystem for RhaiPlugin {
+    fn str_id(&self) -> String {
+        self.str_id.clone()
+    }
+
+    fn raw_call(
+        &self,
+        symbol: &str,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        if abi != RMP_ABI_ID {
+            return Err(BugiError::PluginAbiError(RMP_ABI_ID));
+        }
+
+        let args = match rmp_serde::from_slice::<Dynamic>(param).map_err(SerializeError::from)? {
+            args if args.is_unit() => Array::new(),
+            args => args.into_array().map_err(|ty| {
+                BugiError::PluginCallError(format!("parameters must be a list, found {ty}"))
+            })?,
+        };
+
+        let options = CallFnOptions::new().with_tag(CallTag {
+            id: self.str_id.clone(),
+            symbol: symbol.to_string(),
+            ploxy,
+        });
+        let res = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, symbol, args)
+            .map_err(|err| match *err {
+                EvalAltResult::ErrorTooManyOperations(_) => BugiError::ExecutionLimitExceeded {
+                    id: self.str_id.clone(),
+                    symbol: symbol.to_string(),
+                    limit: ExecutionLimit::CallFuel,
+                },
+                err => BugiError::PluginCallError(format!(
+                    "script error in {}:{symbol}: {err}",
+                    self.str_id
+                )),
+            })?;
+
+        Ok(rmp_serde::to_vec_named(&res).map_err(SerializeError::from)?)
+    }
+
+    fn symbols(&self) -> Vec<PluginSymbol> {
+        let mut symbols = self
+            .ast
+            .iter_functions()
+            .filter(|func| func.access == FnAccess::Public)
+            .map(|func| PluginSymbol {
+                name: func.name.to_string(),
+                abi: Some(RMP_ABI_ID),
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        // overloads share a symbol
+        symbols.dedup();
+        symbols
+    }
+}
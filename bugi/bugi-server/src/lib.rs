@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use bugi::{BugiError, EnvPloxy, LogRecord, Universe};
+use bugi_remote::{send_plugins, Connection, Handler, Stream};
+
+/// Exposes a `Universe` to `RemoteClient`s.
+///
+/// A plugin of the universe calling a plugin the universe doesn't have calls
+/// the universe of the client instead. Log records go to the client.
+#[derive(Clone)]
+pub struct Server {
+    univ: Universe,
+}
+
+impl Server {
+    pub fn new(univ: Universe) -> Self {
+        Self { univ }
+    }
+
+    /// Accept clients until the listener fails
+    pub fn serve_tcp(&self, listener: std::net::TcpListener) -> std::io::Result<()> {
+        for stream in listener.incoming() {
+            self.serve_stream(stream?)?;
+        }
+        Ok(())
+    }
+
+    /// Accept clients until the listener fails
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: std::os::unix::net::UnixListener) -> std::io::Result<()> {
+        for stream in listener.incoming() {
+            self.serve_stream(stream?)?;
+        }
+        Ok(())
+    }
+
+    /// Serve a single client in the background
+    pub fn serve_stream<S: Stream>(&self, mut stream: S) -> std::io::Result<()> {
+        send_plugins(&mut stream, self.univ.list_plugins())?;
+        let server = self.clone();
+        // the connection stays open while the reader thread runs
+        let _conn = Connection::new(
+            stream,
+            Arc::new(
+                move |conn: &Arc<Connection>, id: &str, name: &str, abi, arg: &[u8]| {
+                    server.call(conn, id, name, abi, arg)
+                },
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn call(
+        &self,
+        conn: &Arc<Connection>,
+        id: &str,
+        name: &str,
+        abi: u64,
+        arg: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let ploxy = self.ploxy(conn, id);
+        self.univ
+            .call_raw(id, name, arg, abi, ploxy)
+            .map_err(|err| err.to_string())
+    }
+
+    /// Routes the calls of the plugin `str_id` to the universe, or else to the client
+    fn ploxy(&self, conn: &Arc<Connection>, str_id: &str) -> EnvPloxy {
+        let (univ, call_conn, log_conn) = (self.univ.clone(), conn.clone(), conn.clone());
+        let (server, self_id) = (self.clone(), str_id.to_string());
+        EnvPloxy::new(
+            None,
+            Box::new(move |id, symbol, arg, abi, ploxy| {
+                let id = if id == "self" { self_id.as_str() } else { id };
+                univ.check_call(&ploxy, id, symbol)?;
+                match univ.call_raw(id, symbol, arg, abi, ploxy) {
+                    Err(BugiError::PluginNotFound(missing)) if missing == id => {
+                        let handler = ServerHandler {
+                            server: &server,
+                            conn: &call_conn,
+                        };
+                        call_conn
+                            .call(id, symbol, abi, arg, &handler)
+                            .map_err(|err| {
+                                BugiError::PluginCallError(format!(
+                                    "remote connection error: {err}"
+                                ))
+                            })?
+                            .map_err(BugiError::PluginCallError)
+                    }
+                    res => res,
+                }
+            }),
+            0,
+            Some(Arc::new(move |record: LogRecord| {
+                let _ = log_conn.log(record.level as u32, record.message);
+            })),
+        )
+    }
+}
+
+/// Serves the calls of the client during a call into its universe
+struct ServerHandler<'a> {
+    server: &'a Server,
+    conn: &'a Arc<Connection>,
+}
+
+impl Handler for ServerHandler<'_> {
+    fn call(&self, id: &str, name: &str, abi: u64, detail: &[u8]) -> Result<Vec<u8>, String> {
+        self.server.call(self.conn, id, name, abi, detail)
+    }
+
+    /// records of the client stay in the client
+    fn log(&self, _level: u32, _message: String) {}
+}
@@ -0,0 +1,69 @@
+//! C ABI shared by native (dylib) plugins and their host.
+//!
+//! It mirrors spec-v0 with `@` replaced by `_` in the symbol names,
+//! because ELF linkers read `@` as a symbol version.
+
+use std::ffi::c_void;
+
+/// `bugi_v0_plugin_id(out_len: *mut usize) -> *const u8`: the UTF-8 plugin ID
+pub const DYLIB_PLUG_ID: &str = "bugi_v0_plugin_id";
+
+/// `bugi_v0_low_malloc(len: usize) -> *mut u8`
+pub const DYLIB_LOW_MALLOC: &str = "bugi_v0_low_malloc";
+
+/// `bugi_v0_low_free(ptr: *mut u8, len: usize)`
+pub const DYLIB_LOW_FREE: &str = "bugi_v0_low_free";
+
+/// Prefix of the plugin functions, see `DylibPluginFn`
+pub const DYLIB_PLUGIN_FUNC: &str = "bugi_v0_plugin_function_";
+
+pub type DylibPlugIdFn = unsafe extern "C" fn(out_len: *mut usize) -> *const u8;
+pub type DylibMallocFn = unsafe extern "C" fn(len: usize) -> *mut u8;
+pub type DylibFreeFn = unsafe extern "C" fn(ptr: *mut u8, len: usize);
+
+/// A plugin function.
+/// The argument is allocated with `bugi_v0_low_malloc` and freed by the plugin.
+/// The result is allocated with `bugi_v0_low_malloc` and freed by the host.
+/// A null result means the call failed, after reporting it through `DylibHost::panic`.
+pub type DylibPluginFn = unsafe extern "C" fn(
+    host: *const DylibHost,
+    arg_ptr: *mut u8,
+    arg_len: usize,
+    abi: u64,
+    out_len: *mut usize,
+) -> *mut u8;
+
+/// Host functions, valid during a single call of a plugin function
+#[repr(C)]
+pub struct DylibHost {
+    pub ctx: *const c_void,
+
+    /// Call another plugin in the universe.
+    /// The result is allocated with `bugi_v0_low_malloc`, `out_abi` is `ERROR_ABI_ID` on failure
+    /// and the result is the UTF-8 error message.
+    pub call_univ: unsafe extern "C" fn(
+        ctx: *const c_void,
+        id_ptr: *const u8,
+        id_len: usize,
+        name_ptr: *const u8,
+        name_len: usize,
+        arg_ptr: *const u8,
+        arg_len: usize,
+        abi: u64,
+        out_abi: *mut u64,
+        out_len: *mut usize,
+    ) -> *mut u8,
+
+    /// Write a log record, `level` is a `LogLevel`
+    pub log:
+        unsafe extern "C" fn(ctx: *const c_void, level: u32, msg_ptr: *const u8, msg_len: usize),
+
+    /// Report why the plugin function is about to return null
+    pub panic: unsafe extern "C" fn(
+        ctx: *const c_void,
+        msg_ptr: *const u8,
+        msg_len: usize,
+        loc_ptr: *const u8,
+        loc_len: usize,
+    ),
+}
@@ -1,3 +1,5 @@
+pub mod ffi;
+
 #[derive(thiserror::Error, Debug)]
 pub enum SerializeError {
     #[error("other libraries error")]
@@ -42,6 +44,42 @@ impl SerializeTag for BitcodeTag {
 
 pub const ERROR_ABI_ID: u64 = 0xFF;
 
+/// Level of a log record.
+/// The values are passed to the `bugi@v0` `log` import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    pub fn from_u32(level: u32) -> Option<Self> {
+        match level {
+            1 => Some(LogLevel::Error),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Info),
+            4 => Some(LogLevel::Debug),
+            5 => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogLevel::Error => write!(f, "ERROR"),
+            LogLevel::Warn => write!(f, "WARN"),
+            LogLevel::Info => write!(f, "INFO"),
+            LogLevel::Debug => write!(f, "DEBUG"),
+            LogLevel::Trace => write!(f, "TRACE"),
+        }
+    }
+}
+
 #[cfg(feature = "ser-rmp")]
 pub const RMP_ABI_ID: u64 = 0x00;
 
@@ -0,0 +1,40 @@
+use bugi_dylib_pdk::{call, export, info, macro_prelude::RmpTag, plugin_id};
+
+plugin_id!("dylib-test-plug");
+
+#[export("reverse_string", RmpTag)]
+fn reverse_string(str: String) -> String {
+    str.chars().rev().collect()
+}
+
+#[export("zero_one", RmpTag)]
+fn zero_one() -> String {
+    "TEST".to_string()
+}
+
+#[export("call_univ_test", RmpTag)]
+fn cuniv() -> String {
+    call::<RmpTag, _>("host", "get_string", ()).unwrap()
+}
+
+#[export("call_univ_missing", RmpTag)]
+fn call_univ_missing() -> String {
+    match call::<RmpTag, String>("missing", "nothing", ()) {
+        Ok(_) => "unexpected".to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[export("log_test", RmpTag)]
+fn log_test(name: String) {
+    info!("hello, {}", name);
+    // outside of the plugin call, the record is dropped
+    std::thread::spawn(|| info!("from another thread"))
+        .join()
+        .unwrap();
+}
+
+#[export("panic", RmpTag)]
+fn panic(code: u32) {
+    panic!("panic test: {}", code)
+}
@@ -0,0 +1,50 @@
+use bugi::{serve_stdio, HostPlugin, LogLevel, LogRecord, RmpTag};
+
+fn main() {
+    let mut plug = HostPlugin::new("process-test-plug");
+
+    plug.host_func::<RmpTag, (String,), _>("reverse_string", |(s,), _| {
+        s.chars().rev().collect::<String>()
+    });
+
+    plug.host_func::<RmpTag, (), _>("call_univ_test", |_, ploxy| {
+        ploxy
+            .call_univ::<RmpTag, String>("host", "get_string", ())
+            .unwrap()
+    });
+
+    // calls back into this plugin through the universe
+    plug.host_func::<RmpTag, (u32,), _>("count_down", |(n,), ploxy| match n {
+        0 => 0,
+        n => {
+            ploxy
+                .call_univ::<RmpTag, u32>("process-test-plug", "count_down", (n - 1,))
+                .unwrap()
+                + 1
+        }
+    });
+
+    plug.host_func::<RmpTag, (String,), _>("log_test", |(name,), ploxy| {
+        ploxy.log(LogRecord {
+            plugin_id: String::new(),
+            symbol: String::new(),
+            level: LogLevel::Info,
+            message: format!("hello, {name}"),
+        })
+    });
+
+    // state lost by a reset
+    plug.host_func::<RmpTag, (), _>("counter", |_, ploxy| {
+        let count = ploxy
+            .get_cache()
+            .and_then(|c| c.downcast::<u32>().ok())
+            .map_or(0, |c| *c)
+            + 1;
+        ploxy.set_cache(Box::new(count));
+        count
+    });
+
+    plug.host_func::<RmpTag, (i32,), _>("exit", |(code,), _| -> () { std::process::exit(code) });
+
+    serve_stdio(plug).unwrap();
+}
@@ -0,0 +1,92 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use bugi::{HostPlugin, RmpTag, Universe, WasmRuntime};
+use tokio::sync::Barrier;
+
+/// `host` with async functions
+fn host() -> HostPlugin {
+    let mut host = HostPlugin::new("host");
+    host.host_func_async::<RmpTag, (), _, _>("get_string", |_, _| async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        "TEST".to_string()
+    });
+    host.host_func_async::<RmpTag, (), _, _>("relay", |_, ploxy| async move {
+        let res = ploxy
+            .call_univ_async::<RmpTag, String>("host", "get_string", ())
+            .await
+            .unwrap();
+        format!("{res}!")
+    });
+    let barrier = Arc::new(Barrier::new(2));
+    host.host_func_async::<RmpTag, (), _, _>("rendezvous", move |_, _| {
+        let barrier = barrier.clone();
+        async move {
+            barrier.wait().await;
+        }
+    });
+    host
+}
+
+#[tokio::test]
+async fn host_call_async() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(host())?;
+
+    let res = pref.call_async::<RmpTag, String>("relay", ()).await?;
+    assert_eq!(res, "TEST!");
+
+    // both calls wait on one thread, so neither may block it
+    let (a, b) = tokio::time::timeout(Duration::from_secs(5), async {
+        tokio::join!(
+            pref.call_async::<RmpTag, ()>("rendezvous", ()),
+            pref.call_async::<RmpTag, ()>("rendezvous", ()),
+        )
+    })
+    .await?;
+    a?;
+    b?;
+
+    Ok(())
+}
+
+#[test]
+fn host_call_async_from_sync() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("host");
+    host.host_func_async::<RmpTag, (u32,), _, _>("double", |(n,), _| async move { n * 2 });
+    let pref = univ.add_plugin(host)?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("double", (21,))?, 42);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn wasm_call_async() -> Result<()> {
+    let runtime = WasmRuntime::builder().async_support(true).build()?;
+    let univ = Universe::new();
+    univ.add_plugin(host())?;
+    let wasm = runtime.load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let res = pref
+        .call_async::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))
+        .await?;
+    assert_eq!(res, "DCBA");
+
+    // the plugin is suspended while `host` sleeps
+    let res = pref
+        .call_async::<RmpTag, String>("call_univ_test", ())
+        .await?;
+    assert_eq!(res, "TEST");
+
+    // synchronous calls still work on an async runtime
+    let res = pref.call::<RmpTag, String>("reverse_string", ("AB".to_string(),))?;
+    assert_eq!(res, "BA");
+
+    Ok(())
+}
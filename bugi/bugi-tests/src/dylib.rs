@@ -0,0 +1,90 @@
+use anyhow::Result;
+use bugi::{BugiError, DylibPlugin, HostPlugin, LogLevel, RmpTag, Universe};
+
+fn load() -> Result<DylibPlugin> {
+    let path = format!(
+        "{}/dylib-plug.test{}",
+        env!("CARGO_MANIFEST_DIR"),
+        std::env::consts::DLL_SUFFIX
+    );
+    Ok(unsafe { DylibPlugin::load(path)? })
+}
+
+#[test]
+fn dylib_call() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(load()?)?;
+
+    let res = pref.call::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))?;
+    assert_eq!(res, "DCBA".to_string());
+
+    let res = pref.call::<RmpTag, String>("zero_one", ())?;
+    assert_eq!(res, "TEST".to_string());
+
+    let symbols = pref.symbols()?;
+    assert!(symbols.iter().any(|s| s.name == "reverse_string"));
+    assert_eq!(load()?.plugin_id(), "dylib-test-plug");
+
+    Ok(())
+}
+
+#[test]
+fn dylib_call_univ() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, (), _>("get_string", |_, _| "TEST".to_string());
+    univ.add_plugin(host)?;
+    let records = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    univ.set_log_sink({
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    });
+    let pref = univ.add_plugin(load()?)?;
+
+    assert_eq!(pref.call::<RmpTag, String>("call_univ_test", ())?, "TEST");
+
+    let res = pref.call::<RmpTag, String>("call_univ_missing", ())?;
+    assert!(res.contains("plugin not found: missing"), "{res}");
+
+    // the plugin also logs from a thread of its own, which is dropped
+    pref.call::<RmpTag, ()>("log_test", ("world".to_string(),))?;
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].level, LogLevel::Info);
+    assert_eq!(records[0].symbol, "log_test");
+    assert_eq!(records[0].message, "hello, world");
+
+    Ok(())
+}
+
+#[test]
+fn dylib_panic() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(load()?)?;
+
+    match pref.call::<RmpTag, ()>("panic", (42,)) {
+        Err(BugiError::PluginPanicked {
+            id,
+            symbol,
+            message,
+            location,
+        }) => {
+            assert_eq!(id, "dylib-test-plug");
+            assert_eq!(symbol, "panic");
+            assert_eq!(message, "panic test: 42");
+            assert!(location.contains("src/lib.rs"), "{location}");
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+
+    // a wrong ABI is reported the same way
+    assert!(matches!(
+        pref.call::<bugi::BitcodeTag, String>("zero_one", ()),
+        Err(BugiError::PluginPanicked { .. })
+    ));
+
+    // the plugin is still usable
+    assert_eq!(pref.call::<RmpTag, String>("zero_one", ())?, "TEST");
+
+    Ok(())
+}
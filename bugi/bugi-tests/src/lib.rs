@@ -1,5 +1,14 @@
 #![cfg(test)]
 
+mod async_call;
+mod dylib;
+mod metrics;
+mod middleware;
+mod policy;
+mod process;
+mod remote;
+mod rhai;
+mod trace;
 mod wasm;
 
 use anyhow::*;
@@ -79,3 +88,257 @@ fn self_call_test() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn remove_plugin_test() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("removed");
+    host.host_func::<RmpTag, _, _>("test", |(a,): (i32,), _| a);
+    let pref = univ.add_plugin(host)?;
+
+    univ.remove_plugin("removed")?;
+
+    assert!(matches!(
+        pref.call::<RmpTag, i32>("test", (1,)),
+        Err(BugiError::PluginDropped)
+    ));
+    assert!(matches!(
+        univ.remove_plugin("removed"),
+        Err(BugiError::PluginNotFound(_))
+    ));
+
+    // the string ID can be used again
+    univ.add_plugin(HostPlugin::new("removed"))?;
+
+    Ok(())
+}
+
+#[test]
+fn replace_plugin_test() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("replaced");
+    host.host_func::<RmpTag, _, _>("test", |(a,): (i32,), _| a);
+    let pref = univ.add_plugin(host)?;
+
+    let mut host = HostPlugin::new("replaced");
+    host.host_func::<RmpTag, _, _>("test", |(a,): (i32,), _| a * 2);
+    let new_ref = univ.replace_plugin(host)?;
+
+    assert_eq!(pref.call::<RmpTag, i32>("test", (21,))?, 42);
+    assert_eq!(new_ref.call::<RmpTag, i32>("test", (21,))?, 42);
+
+    assert!(matches!(
+        univ.replace_plugin(HostPlugin::new("unknown")),
+        Err(BugiError::PluginNotFound(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn symbols_test() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("b_host");
+    host.host_func::<RmpTag, _, _>("rmp", |(a,): (i32,), _| a);
+    host.host_func::<BitcodeTag, _, _>("bitcode", |(a,): (i32,), _| a);
+    let pref = univ.add_plugin(host)?;
+    univ.add_plugin(HostPlugin::new("a_host"))?;
+
+    let symbols = vec![
+        PluginSymbol {
+            name: "bitcode".to_string(),
+            abi: Some(BitcodeTag::get_abi_id()),
+        },
+        PluginSymbol {
+            name: "rmp".to_string(),
+            abi: Some(RmpTag::get_abi_id()),
+        },
+    ];
+    assert_eq!(pref.symbols()?, symbols);
+    assert_eq!(
+        univ.list_plugins(),
+        vec![
+            ("a_host".to_string(), vec![]),
+            ("b_host".to_string(), symbols)
+        ]
+    );
+
+    univ.remove_plugin("b_host")?;
+    assert!(matches!(pref.symbols(), Err(BugiError::PluginDropped)));
+
+    Ok(())
+}
+
+#[test]
+fn reentrant_registration_test() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("registrar");
+    let inner = univ.clone();
+    host.host_func::<RmpTag, _, _>("register", move |(name,): (String,), ploxy| {
+        let mut added = HostPlugin::new(&name);
+        added.host_func::<RmpTag, _, _>("double", |(a,): (i32,), _| a * 2);
+        inner.add_plugin(added).unwrap();
+        let res = ploxy
+            .call_univ::<RmpTag, i32>(&name, "double", (21,))
+            .unwrap();
+        inner.remove_plugin(&name).unwrap();
+        res
+    });
+    let pref = univ.add_plugin(host)?;
+
+    assert_eq!(
+        pref.call::<RmpTag, i32>("register", ("added".to_string(),))?,
+        42
+    );
+    assert!(univ.list_plugins().iter().all(|(id, _)| id != "added"));
+
+    Ok(())
+}
+
+#[test]
+fn register_during_call_test() -> Result<()> {
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
+
+    let univ = Universe::new();
+    let (started_tx, started_rx) = mpsc::channel::<()>();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+    let mut host = HostPlugin::new("blocking");
+    host.host_func::<RmpTag, _, _>("wait", move |(): (), _| {
+        started_tx.lock().unwrap().send(()).unwrap();
+        release_rx.lock().unwrap().recv().unwrap();
+    });
+    let pref = univ.add_plugin(host)?;
+
+    let call = std::thread::spawn(move || pref.call::<RmpTag, ()>("wait", ()));
+    started_rx.recv()?;
+
+    // another thread registers while the call is still running
+    let (done_tx, done_rx) = mpsc::channel();
+    let writer = univ.clone();
+    std::thread::spawn(move || {
+        done_tx
+            .send(writer.add_plugin(HostPlugin::new("late")).is_ok())
+            .unwrap();
+    });
+    assert!(done_rx.recv_timeout(Duration::from_secs(5))?);
+
+    release_tx.send(())?;
+    call.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn call_depth_test() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let univ = Universe::new();
+    univ.set_max_call_depth(8);
+    let failure = Arc::new(Mutex::new(None));
+    let mut host = HostPlugin::new("deep");
+    let slot = failure.clone();
+    host.host_func::<RmpTag, _, _>("recurse", move |(n,): (u32,), ploxy| {
+        match ploxy.call_univ::<RmpTag, u32>("self", "recurse", (n + 1,)) {
+            Result::Ok(depth) => depth,
+            Err(err) => {
+                slot.lock().unwrap().get_or_insert(err);
+                n
+            }
+        }
+    });
+    host.host_func::<RmpTag, _, _>("count_down", |(n,): (u32,), ploxy| match n {
+        0 => 0,
+        n => {
+            ploxy
+                .call_univ::<RmpTag, u32>("self", "count_down", (n - 1,))
+                .unwrap()
+                + 1
+        }
+    });
+    let pref = univ.add_plugin(host)?;
+
+    // the host call counts, so 8 frames reach n = 7
+    assert_eq!(pref.call::<RmpTag, u32>("recurse", (0,))?, 7);
+    let Some(BugiError::CallDepthExceeded { limit, chain }) = failure.lock().unwrap().take() else {
+        bail!("depth limit not hit");
+    };
+    assert_eq!(limit, 8);
+    assert_eq!(chain.0.len(), 9);
+    assert!(chain
+        .0
+        .iter()
+        .all(|frame| frame.plugin_id == "deep" && frame.symbol == "recurse"));
+
+    // recursion with changing arguments isn't a cycle
+    assert_eq!(pref.call::<RmpTag, u32>("count_down", (7,))?, 7);
+
+    Ok(())
+}
+
+#[test]
+fn call_cycle_test() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let univ = Universe::new();
+    let failure = Arc::new(Mutex::new(None));
+    let mut ping = HostPlugin::new("ping");
+    ping.host_func::<RmpTag, _, _>("ping", |(n,): (u32,), ploxy| {
+        ploxy
+            .call_univ::<RmpTag, u32>("pong", "pong", (n,))
+            .unwrap()
+    });
+    // pong calls back with the same argument, which would never end
+    let mut pong = HostPlugin::new("pong");
+    let slot = failure.clone();
+    pong.host_func::<RmpTag, _, _>("pong", move |(n,): (u32,), ploxy| {
+        match ploxy.call_univ::<RmpTag, u32>("ping", "ping", (n,)) {
+            Result::Ok(n) => n,
+            Err(err) => {
+                slot.lock().unwrap().get_or_insert(err);
+                n
+            }
+        }
+    });
+    let pref = univ.add_plugin(ping)?;
+    univ.add_plugin(pong)?;
+
+    pref.call::<RmpTag, u32>("ping", (1,))?;
+    let err = failure.lock().unwrap().take().context("no cycle found")?;
+    assert!(matches!(err, BugiError::CallCycle { .. }));
+    assert_eq!(
+        err.to_string(),
+        "call cycle detected: ping:ping -> pong:pong -> ping:ping"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn minimal_plugin_system() -> Result<()> {
+    // only the required methods; the rest have defaults
+    struct Echo;
+    impl PluginSystem for Echo {
+        fn str_id(&self) -> String {
+            "echo".to_string()
+        }
+        fn raw_call(
+            &self,
+            _symbol: &str,
+            param: &[u8],
+            _abi: u64,
+            _ploxy: EnvPloxy,
+        ) -> std::result::Result<Vec<u8>, BugiError> {
+            std::result::Result::Ok(param.to_vec())
+        }
+    }
+
+    let univ = Universe::new();
+    let pref = univ.add_plugin(Echo)?;
+    assert_eq!(pref.call::<RmpTag, (u32,)>("any", (7u32,))?, (7,));
+    assert!(pref.symbols()?.is_empty());
+    pref.reset()?;
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use bugi::{HostPlugin, RmpTag, ToByte, Universe, LATENCY_BUCKETS};
+
+/// `host` with a function calling another one
+fn host() -> HostPlugin {
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, _, _>("double", |(n,): (u32,), _| n * 2);
+    host.host_func::<RmpTag, _, _>("quadruple", |(n,): (u32,), ploxy| {
+        let n = ploxy
+            .call_univ::<RmpTag, u32>("self", "double", (n,))
+            .unwrap();
+        ploxy
+            .call_univ::<RmpTag, u32>("self", "double", (n,))
+            .unwrap()
+    });
+    host
+}
+
+#[test]
+fn count_calls() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(host())?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("quadruple", (3,))?, 12);
+    assert!(pref.call::<RmpTag, u32>("missing", ()).is_err());
+
+    let metrics = univ.metrics();
+    assert_eq!(metrics.calls.len(), 3);
+
+    let quadruple = metrics.get("host", "quadruple").unwrap();
+    assert_eq!((quadruple.calls, quadruple.errors), (1, 0));
+    assert_eq!(quadruple.latency.count(), 1);
+
+    // nested calls count too
+    let double = metrics.get("host", "double").unwrap();
+    assert_eq!((double.calls, double.errors), (2, 0));
+    let arg_len = ToByte::<RmpTag>::to_byte(&(3u32,))?.len() as u64;
+    assert_eq!(double.bytes_in, 2 * arg_len);
+    let res_len = ToByte::<RmpTag>::to_byte(&6u32)?.len() as u64;
+    assert_eq!(double.bytes_out, 2 * res_len);
+
+    let missing = metrics.get("host", "missing").unwrap();
+    assert_eq!(
+        (missing.calls, missing.errors, missing.bytes_out),
+        (1, 1, 0)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn prometheus_text() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(host())?;
+    pref.call::<RmpTag, u32>("double", (1,))?;
+
+    let text = univ.metrics().to_prometheus();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert!(lines.contains(&"# TYPE bugi_calls_total counter"));
+    assert!(lines.contains(&"bugi_calls_total{plugin=\"host\",symbol=\"double\"} 1"));
+    assert!(lines.contains(&"bugi_call_errors_total{plugin=\"host\",symbol=\"double\"} 0"));
+    assert!(lines.contains(&"# TYPE bugi_call_duration_seconds histogram"));
+    assert!(lines.contains(
+        &"bugi_call_duration_seconds_bucket{plugin=\"host\",symbol=\"double\",le=\"+Inf\"} 1"
+    ));
+    assert!(
+        lines.contains(&"bugi_call_duration_seconds_count{plugin=\"host\",symbol=\"double\"} 1")
+    );
+    let buckets = lines
+        .iter()
+        .filter(|line| line.starts_with("bugi_call_duration_seconds_bucket"))
+        .count();
+    assert_eq!(buckets, LATENCY_BUCKETS.len() + 1);
+
+    Ok(())
+}
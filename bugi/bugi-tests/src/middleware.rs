@@ -0,0 +1,130 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bugi::{BugiError, HostPlugin, RmpTag, ToByte, Universe};
+
+/// `host` with a function calling another one
+fn host() -> HostPlugin {
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, _, _>("double", |(n,): (u32,), _| n * 2);
+    host.host_func::<RmpTag, _, _>("quadruple", |(n,): (u32,), ploxy| {
+        let n = ploxy
+            .call_univ::<RmpTag, u32>("self", "double", (n,))
+            .unwrap();
+        ploxy
+            .call_univ::<RmpTag, u32>("host", "double", (n,))
+            .unwrap()
+    });
+    host.host_func::<RmpTag, _, _>("secret", |(): (), _| 0);
+    host
+}
+
+#[test]
+fn audit_calls() -> Result<()> {
+    let univ = Universe::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let before = log.clone();
+    univ.before_call(move |call| {
+        before
+            .lock()
+            .unwrap()
+            .push(format!("before {}:{}", call.plugin_id, call.symbol));
+        Ok(())
+    });
+    let after = log.clone();
+    univ.after_call(move |call, res| {
+        after.lock().unwrap().push(format!(
+            "after {}:{} {}",
+            call.plugin_id,
+            call.symbol,
+            res.is_ok()
+        ));
+    });
+    let pref = univ.add_plugin(host())?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("quadruple", (3,))?, 12);
+
+    // nested calls go through the middleware too, "self" named by its ID
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "before host:quadruple",
+            "before host:double",
+            "after host:double true",
+            "before host:double",
+            "after host:double true",
+            "after host:quadruple true",
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deny_calls() -> Result<()> {
+    let univ = Universe::new();
+    univ.before_call(|call| match call.symbol {
+        "secret" => Err(BugiError::PluginCallError("denied".to_string())),
+        _ => Ok(()),
+    });
+    let pref = univ.add_plugin(host())?;
+
+    assert!(matches!(
+        pref.call::<RmpTag, u32>("secret", ()),
+        Err(BugiError::PluginCallError(msg)) if msg == "denied"
+    ));
+    assert_eq!(pref.call::<RmpTag, u32>("double", (1,))?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn wrap_calls() -> Result<()> {
+    let univ = Universe::new();
+    let depth = Arc::new(Mutex::new((0, 0)));
+    let seen = depth.clone();
+    // outer layer: track how deep the calls nest
+    univ.around_call(move |_, next| {
+        {
+            let mut seen = seen.lock().unwrap();
+            seen.0 += 1;
+            seen.1 = seen.1.max(seen.0);
+        }
+        let res = next.run();
+        seen.lock().unwrap().0 -= 1;
+        res
+    });
+    // inner layer: answer `double` of 0 without the plugin
+    univ.around_call(|call, next| {
+        if call.symbol == "double" && call.arg == ToByte::<RmpTag>::to_byte(&(0u32,))? {
+            return Ok(ToByte::<RmpTag>::to_byte(&100u32)?);
+        }
+        next.run()
+    });
+    let pref = univ.add_plugin(host())?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("double", (0,))?, 100);
+    assert_eq!(pref.call::<RmpTag, u32>("quadruple", (1,))?, 4);
+    assert_eq!(*depth.lock().unwrap(), (0, 2));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_calls() -> Result<()> {
+    let univ = Universe::new();
+    let count = Arc::new(Mutex::new(0));
+    let seen = count.clone();
+    univ.after_call(move |_, _| *seen.lock().unwrap() += 1);
+    univ.before_call(|call| match call.symbol {
+        "secret" => Err(BugiError::PluginCallError("denied".to_string())),
+        _ => Ok(()),
+    });
+    let pref = univ.add_plugin(host())?;
+
+    assert_eq!(pref.call_async::<RmpTag, u32>("quadruple", (1,)).await?, 4);
+    assert!(pref.call_async::<RmpTag, u32>("secret", ()).await.is_err());
+    assert_eq!(*count.lock().unwrap(), 4);
+
+    Ok(())
+}
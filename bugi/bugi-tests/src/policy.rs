@@ -0,0 +1,91 @@
+use anyhow::Result;
+use bugi::{BugiError, Capability, HostPlugin, Overrider, RmpTag, Universe};
+
+/// `internal` host functions
+fn internal() -> HostPlugin {
+    let mut internal = HostPlugin::new("internal");
+    internal.host_func::<RmpTag, _, _>("secret", |(): (), _| 1u32);
+    internal.host_func::<RmpTag, _, _>("public_info", |(): (), _| 2u32);
+    internal
+}
+
+/// a third party plugin reporting what it may call
+fn third() -> HostPlugin {
+    let mut third = HostPlugin::new("third");
+    third.host_func::<RmpTag, _, _>(
+        "try_call",
+        |(id, symbol): (String, String), ploxy| match ploxy.call_univ::<RmpTag, u32>(
+            &id,
+            &symbol,
+            (),
+        ) {
+            Ok(_) => "ok".to_string(),
+            Err(BugiError::PermissionDenied { .. }) => "denied".to_string(),
+            Err(err) => err.to_string(),
+        },
+    );
+    third.host_func::<RmpTag, _, _>("own", |(): (), _| 3u32);
+    third
+}
+
+fn args(id: &str, symbol: &str) -> (String, String) {
+    (id.to_string(), symbol.to_string())
+}
+
+#[test]
+fn capabilities() -> Result<()> {
+    let univ = Universe::new();
+    let internal = univ.add_plugin(internal())?;
+    let pref = univ.add_plugin(third())?;
+    let try_call = |id, symbol| pref.call::<RmpTag, String>("try_call", args(id, symbol));
+
+    // nothing is checked until enforced
+    assert_eq!(try_call("internal", "secret")?, "ok");
+
+    univ.set_capabilities_enforced(true);
+    univ.grant("third", Capability::new("internal", "public_*"));
+    assert_eq!(try_call("internal", "secret")?, "denied");
+    assert_eq!(try_call("internal", "public_info")?, "ok");
+    assert_eq!(try_call("third", "own")?, "ok");
+    assert_eq!(try_call("self", "own")?, "ok");
+
+    // the host itself isn't restricted
+    assert_eq!(internal.call::<RmpTag, u32>("secret", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn capabilities_with_overrider() -> Result<()> {
+    let univ = Universe::new();
+    univ.add_plugin(internal())?;
+    let pref = univ.add_plugin(third())?;
+    univ.set_capabilities_enforced(true);
+
+    let mut over = Overrider::new();
+    over.add::<RmpTag, (), u32>("internal", "secret", |()| 10);
+    over.add::<RmpTag, (), u32>("internal", "public_info", |()| 20);
+    univ.grant("third", Capability::new("internal", "public_info"));
+
+    let res = over.wrap_call::<RmpTag, String>(&pref, "try_call", args("internal", "secret"))?;
+    assert_eq!(res, "denied");
+    let res =
+        over.wrap_call::<RmpTag, String>(&pref, "try_call", args("internal", "public_info"))?;
+    assert_eq!(res, "ok");
+
+    Ok(())
+}
+
+#[test]
+fn capability_patterns() {
+    let cap = Capability::new("internal", "get_*_by_*");
+    assert!(cap.allows("internal", "get_user_by_id"));
+    assert!(cap.allows("internal", "get__by_"));
+    assert!(!cap.allows("internal", "get_user"));
+    assert!(!cap.allows("internal", "set_user_by_id"));
+    assert!(!cap.allows("other", "get_user_by_id"));
+
+    assert!(Capability::new("internal", "*").allows("internal", "anything"));
+    assert!(Capability::new("internal", "exact").allows("internal", "exact"));
+    assert!(!Capability::new("internal", "exact").allows("internal", "exactly"));
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use bugi::{BugiError, HostPlugin, LogLevel, ProcessPlugin, RmpTag, Universe};
+
+fn spawn() -> Result<ProcessPlugin> {
+    let path = format!(
+        "{}/process-plug.test{}",
+        env!("CARGO_MANIFEST_DIR"),
+        std::env::consts::EXE_SUFFIX
+    );
+    ProcessPlugin::spawn(path, Vec::<String>::new())
+}
+
+#[test]
+fn process_call() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(spawn()?)?;
+
+    let res = pref.call::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))?;
+    assert_eq!(res, "DCBA".to_string());
+
+    let symbols = pref.symbols()?;
+    assert!(symbols
+        .iter()
+        .any(|s| s.name == "reverse_string" && s.abi == Some(bugi::RMP_ABI_ID)));
+    assert_eq!(spawn()?.plugin_id(), "process-test-plug");
+
+    // a wrong ABI is an error of the call, not of the connection
+    assert!(matches!(
+        pref.call::<bugi::BitcodeTag, String>("reverse_string", ("ABCD".to_string(),)),
+        Err(BugiError::PluginCallError(_))
+    ));
+    assert_eq!(
+        pref.call::<RmpTag, String>("reverse_string", ("AB".to_string(),))?,
+        "BA"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn process_call_univ() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, (), _>("get_string", |_, _| "TEST".to_string());
+    univ.add_plugin(host)?;
+    let records = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    univ.set_log_sink({
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    });
+    let pref = univ.add_plugin(spawn()?)?;
+
+    assert_eq!(pref.call::<RmpTag, String>("call_univ_test", ())?, "TEST");
+
+    // nested calls into the same process
+    assert_eq!(pref.call::<RmpTag, u32>("count_down", (5,))?, 5);
+
+    pref.call::<RmpTag, ()>("log_test", ("world".to_string(),))?;
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].plugin_id, "process-test-plug");
+    assert_eq!(records[0].symbol, "log_test");
+    assert_eq!(records[0].level, LogLevel::Info);
+    assert_eq!(records[0].message, "hello, world");
+
+    Ok(())
+}
+
+#[test]
+fn process_crash() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(spawn()?)?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("counter", ())?, 1);
+    assert_eq!(pref.call::<RmpTag, u32>("counter", ())?, 2);
+
+    match pref.call::<RmpTag, ()>("exit", (3,)) {
+        Err(BugiError::PluginExited { id, symbol, status }) => {
+            assert_eq!(id, "process-test-plug");
+            assert_eq!(symbol, "exit");
+            assert!(status.contains('3'), "{status}");
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+    assert!(matches!(
+        pref.call::<RmpTag, u32>("counter", ()),
+        Err(BugiError::PluginExited { .. })
+    ));
+
+    // the host survives, and a reset starts a new process
+    pref.reset()?;
+    assert_eq!(pref.call::<RmpTag, u32>("counter", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn process_reset_error() -> Result<()> {
+    let original = format!(
+        "{}/process-plug.test{}",
+        env!("CARGO_MANIFEST_DIR"),
+        std::env::consts::EXE_SUFFIX
+    );
+    let copy = std::env::temp_dir().join(format!(
+        "bugi-process-reset-{}{}",
+        std::process::id(),
+        std::env::consts::EXE_SUFFIX
+    ));
+    std::fs::copy(&original, &copy)?;
+    let univ = Universe::new();
+    let pref = univ.add_plugin(ProcessPlugin::spawn(&copy, Vec::<String>::new())?)?;
+    std::fs::remove_file(&copy)?;
+
+    // the failed restart is reported, and the old process keeps serving
+    assert!(matches!(pref.reset(), Err(BugiError::PluginLoadError(_))));
+    assert_eq!(pref.call::<RmpTag, u32>("counter", ())?, 1);
+
+    Ok(())
+}
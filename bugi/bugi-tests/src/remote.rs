@@ -0,0 +1,182 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bugi::{
+    BugiError, Capability, HostPlugin, LogLevel, LogRecord, RemoteClient, RmpTag, Universe,
+};
+use bugi_server::Server;
+
+/// Universe of the server, with one shared plugin
+fn server() -> Server {
+    let univ = Universe::new();
+    let mut plug = HostPlugin::new("shared");
+
+    plug.host_func::<RmpTag, (String,), _>("reverse_string", |(s,), _| {
+        s.chars().rev().collect::<String>()
+    });
+
+    // returns once two calls are running at the same time
+    let running = Arc::new(AtomicUsize::new(0));
+    plug.host_func::<RmpTag, (), _>("rendezvous", move |_, _| {
+        running.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        while running.load(Ordering::SeqCst) < 2 {
+            if start.elapsed() > Duration::from_secs(5) {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        true
+    });
+
+    // `client` only exists in the universe of the client
+    plug.host_func::<RmpTag, (), _>("call_client", |_, ploxy| {
+        ploxy
+            .call_univ::<RmpTag, String>("client", "get_string", ())
+            .unwrap()
+    });
+
+    // bounces between the server and the client
+    plug.host_func::<RmpTag, (u32,), _>("ping", |(n,), ploxy| match n {
+        0 => 0,
+        n => {
+            ploxy
+                .call_univ::<RmpTag, u32>("client", "pong", (n - 1,))
+                .unwrap()
+                + 1
+        }
+    });
+
+    plug.host_func::<RmpTag, (String,), _>("log_test", |(name,), ploxy| {
+        ploxy.log(LogRecord {
+            plugin_id: String::new(),
+            symbol: String::new(),
+            level: LogLevel::Info,
+            message: format!("hello, {name}"),
+        })
+    });
+
+    univ.add_plugin(plug).unwrap();
+    Server::new(univ)
+}
+
+/// Universe of a client, to add `shared` to
+fn client() -> Result<Universe> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("client");
+    host.host_func::<RmpTag, (), _>("get_string", |_, _| "TEST".to_string());
+    host.host_func::<RmpTag, (u32,), _>("pong", |(n,), ploxy| match n {
+        0 => 0,
+        n => {
+            ploxy
+                .call_univ::<RmpTag, u32>("shared", "ping", (n - 1,))
+                .unwrap()
+                + 1
+        }
+    });
+    univ.add_plugin(host)?;
+    Ok(univ)
+}
+
+#[test]
+fn remote_tcp() -> Result<()> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = server();
+    std::thread::spawn(move || server.serve_tcp(listener));
+
+    let remote = RemoteClient::connect_tcp(addr)?;
+    assert_eq!(remote.plugins().len(), 1);
+    let univ = client()?;
+    let pref = univ.add_plugin(remote.plugin("shared").unwrap())?;
+
+    let res = pref.call::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))?;
+    assert_eq!(res, "DCBA".to_string());
+    assert!(pref.symbols()?.iter().any(|s| s.name == "rendezvous"));
+
+    // calls of several threads run at once
+    std::thread::scope(|s| {
+        let calls = [(); 2].map(|_| s.spawn(|| pref.call::<RmpTag, bool>("rendezvous", ())));
+        for call in calls {
+            assert!(call.join().unwrap().unwrap());
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn remote_unix() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("bugi-remote-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = std::os::unix::net::UnixListener::bind(&path)?;
+    let server = server();
+    std::thread::spawn(move || server.serve_unix(listener));
+
+    let remote = RemoteClient::connect_unix(&path)?;
+    let univ = client()?;
+    let pref = univ.add_plugin(remote.plugin("shared").unwrap())?;
+    let records = Arc::new(Mutex::new(Vec::new()));
+    univ.set_log_sink({
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    });
+
+    // reverse calls into the universe of the client
+    assert_eq!(pref.call::<RmpTag, String>("call_client", ())?, "TEST");
+    assert_eq!(pref.call::<RmpTag, u32>("ping", (6,))?, 6);
+
+    pref.call::<RmpTag, ()>("log_test", ("world".to_string(),))?;
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].plugin_id, "shared");
+    assert_eq!(records[0].symbol, "log_test");
+    assert_eq!(records[0].message, "hello, world");
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn remote_capabilities() -> Result<()> {
+    let univ = Universe::new();
+    let mut internal = HostPlugin::new("internal");
+    internal.host_func::<RmpTag, (), _>("secret", |_, _| 1u32);
+    internal.host_func::<RmpTag, (), _>("public_info", |_, _| 2u32);
+    univ.add_plugin(internal)?;
+
+    // reports how a call of `internal` went
+    let mut third = HostPlugin::new("third");
+    third.host_func::<RmpTag, (String,), _>("try_call", |(symbol,), ploxy| {
+        match ploxy.call_univ::<RmpTag, u32>("internal", &symbol, ()) {
+            Ok(_) => "ok".to_string(),
+            Err(BugiError::PermissionDenied { .. }) => "denied".to_string(),
+            Err(err) => err.to_string(),
+        }
+    });
+    univ.add_plugin(third)?;
+    univ.set_capabilities_enforced(true);
+    univ.grant("third", Capability::new("internal", "public_*"));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = Server::new(univ);
+    std::thread::spawn(move || server.serve_tcp(listener));
+
+    let remote = RemoteClient::connect_tcp(addr)?;
+    let client = Universe::new();
+    let pref = client.add_plugin(remote.plugin("third").unwrap())?;
+
+    let try_call = |symbol: &str| pref.call::<RmpTag, String>("try_call", (symbol.to_string(),));
+    assert_eq!(try_call("secret")?, "denied");
+    assert_eq!(try_call("public_info")?, "ok");
+
+    Ok(())
+}
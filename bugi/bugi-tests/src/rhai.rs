@@ -0,0 +1,116 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bugi::{BugiError, ExecutionLimit, HostPlugin, LogLevel, RhaiPlugin, RmpTag, Universe};
+
+const SCRIPT: &str = r#"
+fn reverse_string(s) {
+    let reversed = "";
+    for c in s.chars() {
+        reversed = c + reversed;
+    }
+    reversed
+}
+
+fn add(a, b) {
+    a + b
+}
+
+fn range(n) {
+    let list = [];
+    for i in 0..n {
+        list.push(helper(i));
+    }
+    list
+}
+
+private fn helper(i) {
+    i * 2
+}
+
+fn call_host(name) {
+    call_univ("host", "greet", [name]) + call_univ("host", "get_string")
+}
+
+fn log_test(name) {
+    log("info", `hello, ${name}`);
+}
+
+fn spin() {
+    loop {}
+}
+"#;
+
+#[test]
+fn rhai_call() -> Result<()> {
+    let univ = Universe::new();
+    let pref = univ.add_plugin(RhaiPlugin::new("rhai-plug", SCRIPT)?)?;
+
+    let res = pref.call::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))?;
+    assert_eq!(res, "DCBA");
+    assert_eq!(pref.call::<RmpTag, i64>("add", (1, 2))?, 3);
+    assert_eq!(pref.call::<RmpTag, Vec<i64>>("range", (3,))?, vec![0, 2, 4]);
+
+    let symbols = pref.symbols()?;
+    assert!(symbols.iter().any(|s| s.name == "range"));
+    assert!(!symbols.iter().any(|s| s.name == "helper"));
+
+    // only RmpTag is understood
+    assert!(matches!(
+        pref.call::<bugi::BitcodeTag, i64>("add", (1, 2)),
+        Err(BugiError::PluginAbiError(_))
+    ));
+    assert!(matches!(
+        pref.call::<RmpTag, i64>("missing", ()),
+        Err(BugiError::PluginCallError(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn rhai_call_univ() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, (String,), _>("greet", |(name,), _| format!("hello, {name}. "));
+    host.host_func::<RmpTag, (), _>("get_string", |_, _| "TEST".to_string());
+    univ.add_plugin(host)?;
+    let records = Arc::new(Mutex::new(Vec::new()));
+    univ.set_log_sink({
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    });
+    let pref = univ.add_plugin(RhaiPlugin::new("rhai-plug", SCRIPT)?)?;
+
+    let res = pref.call::<RmpTag, String>("call_host", ("world".to_string(),))?;
+    assert_eq!(res, "hello, world. TEST");
+
+    pref.call::<RmpTag, ()>("log_test", ("world".to_string(),))?;
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].plugin_id, "rhai-plug");
+    assert_eq!(records[0].symbol, "log_test");
+    assert_eq!(records[0].level, LogLevel::Info);
+    assert_eq!(records[0].message, "hello, world");
+
+    Ok(())
+}
+
+#[test]
+fn rhai_limit() -> Result<()> {
+    let mut plug = RhaiPlugin::new("rhai-plug", SCRIPT)?;
+    plug.set_max_operations(10_000);
+    let univ = Universe::new();
+    let pref = univ.add_plugin(plug)?;
+
+    assert!(matches!(
+        pref.call::<RmpTag, ()>("spin", ()),
+        Err(BugiError::ExecutionLimitExceeded {
+            limit: ExecutionLimit::CallFuel,
+            ..
+        })
+    ));
+    assert_eq!(pref.call::<RmpTag, i64>("add", (1, 2))?, 3);
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use bugi::{HostPlugin, RmpTag, Universe, WasmPlugin};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+
+/// A span as the recorder saw it
+#[derive(Debug, Default)]
+struct SpanData {
+    name: &'static str,
+    parent: Option<u64>,
+    fields: HashMap<&'static str, String>,
+}
+
+impl Visit for SpanData {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.fields.insert(field.name(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name(), value.to_string());
+    }
+}
+
+/// Keeps every span, with the span entered on the thread as its parent
+#[derive(Default, Clone)]
+struct Recorder {
+    spans: Arc<Mutex<HashMap<u64, SpanData>>>,
+    stack: Arc<Mutex<Vec<u64>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    /// spans with `name`, ordered by creation
+    fn find(&self, name: &str) -> Vec<(u64, Option<u64>, HashMap<&'static str, String>)> {
+        let spans = self.spans.lock().unwrap();
+        let mut found = spans
+            .iter()
+            .filter(|(_, span)| span.name == name)
+            .map(|(id, span)| (*id, span.parent, span.fields.clone()))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(id, _, _)| *id);
+        found
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut span = SpanData {
+            name: attrs.metadata().name(),
+            parent: self.stack.lock().unwrap().last().copied(),
+            ..Default::default()
+        };
+        attrs.record(&mut span);
+        self.spans.lock().unwrap().insert(id, span);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, id: &Id, values: &Record<'_>) {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            values.record(span);
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, id: &Id) {
+        self.stack.lock().unwrap().push(id.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.stack.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn call_spans() -> Result<()> {
+    let univ = Universe::new();
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, (), _>("get_string", |_, _| "TEST".to_string());
+    univ.add_plugin(host)?;
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let recorder = Recorder::default();
+    let res = tracing::subscriber::with_default(recorder.clone(), || {
+        pref.call::<RmpTag, String>("call_univ_test", ())
+    })?;
+    assert_eq!(res, "TEST");
+
+    // host -> wasm -> host, each hop nested in the one before
+    let [(call, None, fields)] = &recorder.find("plugin_call")[..] else {
+        panic!("expected one plugin_call span");
+    };
+    assert_eq!(fields["plugin"], "wasm-test-plug");
+    assert_eq!(fields["symbol"], "call_univ_test");
+    assert!(fields["duration_us"].parse::<u64>().is_ok());
+
+    let [(wasm_call, Some(parent), _)] = &recorder.find("wasm_call")[..] else {
+        panic!("expected one wasm_call span");
+    };
+    assert_eq!(parent, call);
+
+    let [(call_univ, Some(parent), fields)] = &recorder.find("call_univ")[..] else {
+        panic!("expected one call_univ span");
+    };
+    assert_eq!(parent, wasm_call);
+    assert_eq!(fields["plugin"], "host");
+    assert_eq!(fields["symbol"], "get_string");
+    assert_eq!(fields["caller"], "wasm-test-plug:call_univ_test");
+
+    let [(_, Some(parent), fields)] = &recorder.find("universe_call")[..] else {
+        panic!("expected one universe_call span");
+    };
+    assert_eq!(parent, call_univ);
+    assert_eq!(fields["plugin"], "host");
+    assert!(fields.contains_key("duration_us"));
+
+    Ok(())
+}
@@ -1,5 +1,9 @@
 use anyhow::Result;
-use bugi::{HostPlugin, RmpTag, Universe, WasmPlugin};
+use bugi::{
+    BugiError, Capability, ExecutionLimit, HostPlugin, InstanceAllocation, LogLevel, LogRecord,
+    OptLevel, PluginMetadata, RmpTag, SpecViolations, SymbolSignature, Universe, WasiConfig,
+    WasiDir, WasiOutput, WasmPlugin, WasmPluginConfig, WasmRuntime,
+};
 
 #[test]
 fn wasm_call() -> Result<()> {
@@ -65,3 +69,767 @@ fn call_univ_test() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn wasm_state_persists() -> Result<()> {
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 2);
+
+    pref.reset()?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    Ok(())
+}
+
+/// copy the test plugin to a file that can be rewritten
+fn watched_copy(name: &str) -> Result<std::path::PathBuf> {
+    let path = std::env::temp_dir().join(format!("bugi-{}-{}.wasm", std::process::id(), name));
+    std::fs::copy(
+        format!("{}/wasm-plug.test.wasm", env!("CARGO_MANIFEST_DIR")),
+        &path,
+    )?;
+    Ok(path)
+}
+
+/// rewrite the file and move its modified time forward
+fn rewrite(path: &std::path::Path, bin: &[u8]) -> Result<()> {
+    std::fs::write(path, bin)?;
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(10))?;
+    Ok(())
+}
+
+#[test]
+fn wasm_hot_reload() -> Result<()> {
+    let path = watched_copy("hot-reload")?;
+    let univ = Universe::new();
+    let (pref, watcher) = univ.add_wasm_watched(&path)?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 2);
+    assert!(!watcher.check()?);
+
+    rewrite(&path, &std::fs::read(&path)?)?;
+    assert!(watcher.check()?);
+
+    // the new module starts with fresh state
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn wasm_watcher_stops_promptly() -> Result<()> {
+    let path = watched_copy("stop")?;
+    let univ = Universe::new();
+    let (_pref, mut watcher) = univ.add_wasm_watched(&path)?;
+
+    // stopping doesn't wait out the interval
+    watcher.spawn(std::time::Duration::from_secs(3600), |_| {});
+    let start = std::time::Instant::now();
+    drop(watcher);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn wasm_hot_reload_keeps_limits() -> Result<()> {
+    let path = watched_copy("hot-reload-limits")?;
+    let univ = Universe::new();
+    let runtime = WasmRuntime::builder().fuel(true).build()?;
+    let (pref, watcher) = univ.add_wasm_watched_with(&path, move |path| {
+        let mut wasm = runtime.load(path)?;
+        wasm.set_fuel_per_call(Some(1_000_000))?;
+        Ok(wasm)
+    })?;
+    let spin_limited = || {
+        matches!(
+            pref.call::<RmpTag, ()>("spin", ()),
+            Err(BugiError::ExecutionLimitExceeded {
+                limit: ExecutionLimit::CallFuel,
+                ..
+            })
+        )
+    };
+    assert!(spin_limited());
+
+    rewrite(&path, &std::fs::read(&path)?)?;
+    assert!(watcher.check()?);
+
+    // the reloaded plugin is as limited as the first one
+    assert!(spin_limited());
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn wasm_hot_reload_id_mismatch() -> Result<()> {
+    let path = watched_copy("id-mismatch")?;
+    let univ = Universe::new();
+    let (pref, watcher) = univ.add_wasm_watched(&path)?;
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    let bin = std::fs::read(&path)?;
+    let pos = bin
+        .windows(b"wasm-test-plug".len())
+        .position(|w| w == b"wasm-test-plug")
+        .unwrap();
+    let mut patched = bin.clone();
+    patched[pos..pos + b"wasm-test-plug".len()].copy_from_slice(b"wasm-test-plux");
+    rewrite(&path, &patched)?;
+
+    assert!(matches!(
+        watcher.check(),
+        Err(BugiError::PluginIdMismatch(_, _))
+    ));
+
+    // the old plugin keeps running
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 2);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn call_univ_error_test() -> Result<()> {
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let res = pref.call::<RmpTag, String>("call_univ_missing", ())?;
+
+    assert!(res.contains("plugin not found: missing"), "{res}");
+
+    Ok(())
+}
+
+#[test]
+fn wasm_trap_test() -> Result<()> {
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    match pref.call::<RmpTag, ()>("trap", ()) {
+        Err(BugiError::PluginTrap { id, symbol, .. }) => {
+            assert_eq!(id, "wasm-test-plug");
+            assert_eq!(symbol, "trap");
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+
+    // the plugin is still usable after a trap
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn wasm_panic_test() -> Result<()> {
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    match pref.call::<RmpTag, ()>("panic", (42,)) {
+        Err(BugiError::PluginPanicked {
+            id,
+            symbol,
+            message,
+            location,
+        }) => {
+            assert_eq!(id, "wasm-test-plug");
+            assert_eq!(symbol, "panic");
+            assert_eq!(message, "panic test: 42");
+            assert!(location.contains("src/lib.rs"), "{location}");
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+
+    // a later trap isn't mistaken for the panic
+    assert!(matches!(
+        pref.call::<RmpTag, ()>("trap", ()),
+        Err(BugiError::PluginTrap { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn wasm_fuel_limit() -> Result<()> {
+    let univ = Universe::new();
+    let runtime = WasmRuntime::builder().fuel(true).build()?;
+    let mut wasm = runtime.load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    wasm.set_fuel_per_call(Some(1_000_000))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    assert!(matches!(
+        pref.call::<RmpTag, ()>("spin", ()),
+        Err(BugiError::ExecutionLimitExceeded {
+            limit: ExecutionLimit::CallFuel,
+            ..
+        })
+    ));
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn wasm_fuel_total_limit() -> Result<()> {
+    let univ = Universe::new();
+    let runtime = WasmRuntime::builder().fuel(true).build()?;
+    let mut wasm = runtime.load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    wasm.set_fuel_total(Some(100_000))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let err = loop {
+        if let Err(err) = pref.call::<RmpTag, u32>("count_up", ()) {
+            break err;
+        }
+    };
+    assert!(matches!(
+        err,
+        BugiError::ExecutionLimitExceeded {
+            limit: ExecutionLimit::TotalFuel,
+            ..
+        }
+    ));
+
+    // reset refills the fuel
+    pref.reset()?;
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn wasm_fuel_total_reentrant() -> Result<()> {
+    let univ = Universe::new();
+    let runtime = WasmRuntime::builder().fuel(true).build()?;
+    let mut wasm = runtime.load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    wasm.set_fuel_total(Some(10_000_000))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    // the host calls back into the plugin while its outer call still runs
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, (), _>("get_string", |_, ploxy| {
+        match ploxy.call_univ::<RmpTag, u32>("wasm-test-plug", "count_up", ()) {
+            Ok(n) => n.to_string(),
+            Err(err) => err.to_string(),
+        }
+    });
+    univ.add_plugin(host)?;
+
+    assert_eq!(pref.call::<RmpTag, String>("call_univ_test", ())?, "1");
+
+    Ok(())
+}
+
+#[test]
+fn wasm_deadline() -> Result<()> {
+    let univ = Universe::new();
+    let runtime = WasmRuntime::builder().epoch_interruption(true).build()?;
+    let mut wasm = runtime.load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    wasm.set_deadline(Some(std::time::Duration::from_millis(50)))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    assert!(matches!(
+        pref.call::<RmpTag, ()>("spin", ()),
+        Err(BugiError::ExecutionLimitExceeded {
+            limit: ExecutionLimit::Deadline,
+            ..
+        })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn wasm_limits_need_runtime_support() -> Result<()> {
+    let mut wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    assert!(wasm.set_fuel_per_call(Some(1_000_000)).is_err());
+    assert!(wasm.set_fuel_total(Some(1_000_000)).is_err());
+    assert!(wasm
+        .set_deadline(Some(std::time::Duration::from_millis(50)))
+        .is_err());
+    // clearing a limit is always fine
+    wasm.set_fuel_per_call(None)?;
+    wasm.set_deadline(None)?;
+
+    let univ = Universe::new();
+    let pref = univ.add_plugin(wasm)?;
+    assert_eq!(pref.call::<RmpTag, u32>("count_up", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn wasm_memory_limit() -> Result<()> {
+    let univ = Universe::new();
+    let mut wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    wasm.set_config(WasmPluginConfig {
+        max_memory_pages: Some(64),
+        ..Default::default()
+    });
+    let pref = univ.add_plugin(wasm)?;
+
+    assert_eq!(pref.call::<RmpTag, u32>("alloc_bytes", (1024u32,))?, 1024);
+    assert!(matches!(
+        pref.call::<RmpTag, u32>("alloc_bytes", (16 * 1024 * 1024u32,)),
+        Err(BugiError::ResourceLimitExceeded { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn wasm_runtime_builder() -> Result<()> {
+    let runtime = WasmRuntime::builder()
+        .allocation(InstanceAllocation::OnDemand)
+        .opt_level(OptLevel::None)
+        .simd(false)
+        .build()?;
+
+    let univ = Universe::new();
+    let wasm = runtime.load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let res = pref.call::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))?;
+
+    assert_eq!(res, "DCBA".to_string());
+
+    Ok(())
+}
+
+#[test]
+fn wasm_compile_cache() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("bugi-cache-{}", std::process::id()));
+    let path = format!("{}/wasm-plug.test.wasm", env!("CARGO_MANIFEST_DIR"));
+
+    let compiled = WasmPlugin::precompile(&path, &dir)?;
+    assert!(compiled.exists());
+
+    // the default configuration finds the precompiled module
+    let runtime = WasmRuntime::builder().cache_dir(&dir).build()?;
+    assert_eq!(runtime.precompile(&path)?, compiled);
+
+    let univ = Universe::new();
+    let pref = univ.add_plugin(runtime.load(&path)?)?;
+    let res = pref.call::<RmpTag, String>("reverse_string", ("ABCD".to_string(),))?;
+    assert_eq!(res, "DCBA".to_string());
+
+    // a different configuration gets its own entry
+    let runtime = WasmRuntime::builder()
+        .opt_level(OptLevel::None)
+        .cache_dir(&dir)
+        .build()?;
+    assert_ne!(runtime.precompile(&path)?, compiled);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn wasm_spec_validation() -> Result<()> {
+    let bin = wat::parse_str(
+        r#"
+        (module
+            (import "env" "abort" (func))
+            (import "bugi@v0" "call_univ" (func (param i32) (result i64)))
+            (memory (export "memory") 1)
+            (func (export "bugi@v0_low_free") (param i32 i32))
+            (func (export "bugi@v0_plugin_function_bad") (param i32 i32) (result i64)
+                i64.const 0))
+        "#,
+    )?;
+
+    let err = WasmPlugin::load_bin(&bin).err().unwrap();
+    let violations = &err.downcast_ref::<SpecViolations>().unwrap().0;
+    assert_eq!(violations.len(), 5, "{violations:?}");
+    assert!(violations.iter().any(|v| v.contains("bugi@v0_plugin_id")));
+    assert!(violations.iter().any(|v| v.contains("bugi@v0_low_malloc")));
+    assert!(violations
+        .iter()
+        .any(|v| v.contains("bugi@v0_plugin_function_bad")));
+    assert!(violations.iter().any(|v| v.contains("env::abort")));
+    assert!(violations.iter().any(|v| v.contains("call_univ")));
+
+    // the conforming test plugin passes
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    assert_eq!(wasm.plugin_id(), "wasm-test-plug");
+
+    Ok(())
+}
+
+#[test]
+fn wasm_malformed_module() -> Result<()> {
+    let bin = std::fs::read(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+
+    // a truncated module is a load error, not a module without custom sections
+    let err = WasmPlugin::load_bin(&bin[..bin.len() / 2]).err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<BugiError>(),
+        Some(BugiError::PluginLoadError(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn wasm_symbols() -> Result<()> {
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let symbols = pref.symbols()?;
+    let reverse = symbols.iter().find(|s| s.name == "reverse_string").unwrap();
+    assert_eq!(reverse.abi, None);
+    assert!(symbols.iter().any(|s| s.name == "count_up"));
+    assert!(!symbols.iter().any(|s| s.name.starts_with("bugi@v0")));
+
+    Ok(())
+}
+
+#[test]
+fn wasm_manifest() -> Result<()> {
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    let metadata = pref.metadata()?;
+    // the version comes from the package of the plugin
+    assert_eq!(metadata.version.as_deref(), Some("0.1.0"));
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("plugin for the bugi tests")
+    );
+    assert_eq!(metadata.authors, vec!["bugi developers"]);
+    assert_eq!(metadata.dependencies, vec!["host"]);
+    assert_eq!(
+        metadata.capabilities,
+        vec![Capability::new("host", "get_*")]
+    );
+    assert_eq!(
+        metadata.symbols,
+        vec![
+            SymbolSignature {
+                name: "reverse_string".to_string(),
+                params: vec!["String".to_string()],
+                result: "String".to_string(),
+            },
+            SymbolSignature {
+                name: "one_zero".to_string(),
+                params: vec!["String".to_string()],
+                result: "()".to_string(),
+            },
+            SymbolSignature {
+                name: "call_univ_test".to_string(),
+                params: vec![],
+                result: "String".to_string(),
+            },
+        ]
+    );
+
+    // grant what the plugin asks for before calling it
+    univ.set_capabilities_enforced(true);
+    for cap in metadata.capabilities {
+        univ.grant("wasm-test-plug", cap);
+    }
+    let mut host = HostPlugin::new("host");
+    host.host_func::<RmpTag, (), _>("get_string", |_, _| "TEST".to_string());
+    univ.add_plugin(host)?;
+    assert_eq!(pref.call::<RmpTag, String>("call_univ_test", ())?, "TEST");
+
+    // plugins without a manifest declare nothing
+    assert_eq!(
+        univ.add_plugin(HostPlugin::new("empty"))?.metadata()?,
+        PluginMetadata::default()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn wasm_manifest_validation() -> Result<()> {
+    let plug = |manifest: &str| {
+        wat::parse_str(format!(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "bugi@v0_low_malloc") (param i32) (result i32)
+                    i32.const 1024)
+                (func (export "bugi@v0_low_free") (param i32 i32))
+                (func (export "bugi@v0_plugin_function_hello") (param i32 i32 i64) (result i64)
+                    i64.const 0)
+                (@custom "bugi@v0_plugin_id" "manifest-test")
+                (@custom "bugi@v0_plugin_manifest" "{manifest}"))
+            "#
+        ))
+    };
+
+    let err = WasmPlugin::load_bin(&plug("\\01")?).err().unwrap();
+    let violations = &err.downcast_ref::<SpecViolations>().unwrap().0;
+    assert_eq!(violations.len(), 1, "{violations:?}");
+    assert!(violations[0].contains("bugi@v0_plugin_manifest"));
+
+    // {"symbols": [{"name": "nope", "params": [], "result": "()"}]}
+    let manifest = "\\81\\a7symbols\\91\\83\\a4name\\a4nope\\a6params\\90\\a6result\\a2()";
+    let err = WasmPlugin::load_bin(&plug(manifest)?).err().unwrap();
+    let violations = &err.downcast_ref::<SpecViolations>().unwrap().0;
+    assert_eq!(violations.len(), 1, "{violations:?}");
+    assert!(violations[0].contains("bugi@v0_plugin_function_nope"));
+
+    // the manifest drifted from the exports: "hello" is still there, "gone" isn't
+    // {"symbols": [{"name": "hello", ..}, {"name": "gone", ..}]}
+    let manifest = "\\81\\a7symbols\\92\
+        \\83\\a4name\\a5hello\\a6params\\90\\a6result\\a2()\
+        \\83\\a4name\\a4gone\\a6params\\90\\a6result\\a2()";
+    let err = WasmPlugin::load_bin(&plug(manifest)?).err().unwrap();
+    let violations = &err.downcast_ref::<SpecViolations>().unwrap().0;
+    assert_eq!(violations.len(), 1, "{violations:?}");
+    assert!(violations[0].contains("bugi@v0_plugin_function_gone"));
+
+    Ok(())
+}
+
+const WASI_PLUG: &str = r#"
+    (module
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "path_open"
+            (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 16) "hello wasi\n")
+        (data (i32.const 32) "new.txt")
+        (data (i32.const 64) "\a2ok")
+        (func (export "bugi@v0_low_malloc") (param i32) (result i32)
+            i32.const 1024)
+        (func (export "bugi@v0_low_free") (param i32 i32))
+        (func (export "bugi@v0_plugin_function_hello") (param i32 i32 i64) (result i64)
+            (i32.store (i32.const 0) (i32.const 16))
+            (i32.store (i32.const 4) (i32.const 11))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+            ;; "ok" at 64
+            i64.const 0x4000000003)
+        ;; create "new.txt" (fd_read | fd_write) in the first preopened directory, returns the errno
+        (func (export "bugi@v0_plugin_function_create") (param i32 i32 i64) (result i64)
+            (i32.store8 (i32.const 96)
+                (call $path_open (i32.const 3) (i32.const 0) (i32.const 32) (i32.const 7)
+                    (i32.const 1) (i64.const 0x42) (i64.const 0x42)
+                    (i32.const 0) (i32.const 8)))
+            i64.const 0x6000000001)
+        (@custom "bugi@v0_plugin_id" "wasi-test"))
+"#;
+
+#[test]
+fn wasm_wasi() -> Result<()> {
+    let bin = wat::parse_str(WASI_PLUG)?;
+
+    // nothing is granted by default
+    let univ = Universe::new();
+    let wasm = WasmPlugin::load_bin(&bin)?;
+    assert!(wasm.wasi_stdout().is_none());
+    let pref = univ.add_plugin(wasm)?;
+    assert_eq!(pref.call::<RmpTag, String>("hello", ())?, "ok");
+
+    let univ = Universe::new();
+    let mut wasm = WasmPlugin::load_bin(&bin)?;
+    wasm.set_wasi(WasiConfig {
+        stdout: WasiOutput::Capture(1024),
+        ..Default::default()
+    })?;
+    let stdout = wasm.wasi_stdout().unwrap();
+    let pref = univ.add_plugin(wasm)?;
+    pref.call::<RmpTag, String>("hello", ())?;
+    pref.call::<RmpTag, String>("hello", ())?;
+    assert_eq!(stdout.contents(), b"hello wasi\nhello wasi\n");
+
+    // read-only directories refuse to create files
+    let dir = std::env::temp_dir().join(format!("bugi-wasi-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    for (read_only, errno) in [(true, 63 /* EPERM */), (false, 0)] {
+        let univ = Universe::new();
+        let mut wasm = WasmPlugin::load_bin(&bin)?;
+        wasm.set_wasi(WasiConfig {
+            preopened_dirs: vec![WasiDir {
+                host_path: dir.clone(),
+                guest_path: "/data".to_string(),
+                read_only,
+            }],
+            ..Default::default()
+        })?;
+        let pref = univ.add_plugin(wasm)?;
+        assert_eq!(pref.call::<RmpTag, u8>("create", ())?, errno);
+        assert_eq!(dir.join("new.txt").exists(), !read_only);
+    }
+    std::fs::remove_dir_all(&dir)?;
+
+    // a directory that can't be opened is rejected up front
+    let mut wasm = WasmPlugin::load_bin(&bin)?;
+    let res = wasm.set_wasi(WasiConfig {
+        preopened_dirs: vec![WasiDir {
+            host_path: "/nonexistent/bugi".into(),
+            guest_path: "/data".to_string(),
+            read_only: true,
+        }],
+        ..Default::default()
+    });
+    assert!(res.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn wasm_wasi_log() -> Result<()> {
+    let univ = Universe::new();
+    let records = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    univ.set_log_sink({
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    });
+    let mut wasm = WasmPlugin::load_bin(&wat::parse_str(WASI_PLUG)?)?;
+    wasm.set_wasi(WasiConfig {
+        stdout: WasiOutput::Log(LogLevel::Info),
+        ..Default::default()
+    })?;
+    assert!(wasm.wasi_stdout().is_none());
+    let pref = univ.add_plugin(wasm)?;
+
+    pref.call::<RmpTag, String>("hello", ())?;
+    pref.call::<RmpTag, String>("hello", ())?;
+
+    let record = LogRecord {
+        plugin_id: "wasi-test".to_string(),
+        symbol: "hello".to_string(),
+        level: LogLevel::Info,
+        message: "hello wasi".to_string(),
+    };
+    assert_eq!(*records.lock().unwrap(), vec![record.clone(), record]);
+
+    Ok(())
+}
+
+#[test]
+fn wasm_log() -> Result<()> {
+    let univ = Universe::new();
+    let records = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    univ.set_log_sink({
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    });
+    let wasm = WasmPlugin::load(format!(
+        "{}/wasm-plug.test.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    ))?;
+    let pref = univ.add_plugin(wasm)?;
+
+    pref.call::<RmpTag, ()>("log_test", ("world".to_string(),))?;
+
+    let record = |level, message: &str| LogRecord {
+        plugin_id: "wasm-test-plug".to_string(),
+        symbol: "log_test".to_string(),
+        level,
+        message: message.to_string(),
+    };
+    assert_eq!(
+        *records.lock().unwrap(),
+        vec![
+            record(LogLevel::Info, "hello, world"),
+            record(LogLevel::Error, "bye, world"),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn wasm_import_in_start() -> Result<()> {
+    for import in [
+        r#"(import "bugi@v0" "call_univ" (func $host (param i32 i32) (result i64)))
+           (func $start (drop (call $host (i32.const 0) (i32.const 0))))"#,
+        r#"(import "bugi@v0" "log" (func $host (param i32 i32 i32)))
+           (func $start (call $host (i32.const 3) (i32.const 0) (i32.const 0)))"#,
+    ] {
+        let bin = wat::parse_str(format!(
+            r#"
+            (module
+                {import}
+                (memory (export "memory") 1)
+                (start $start)
+                (func (export "bugi@v0_low_malloc") (param i32) (result i32)
+                    i32.const 1024)
+                (func (export "bugi@v0_low_free") (param i32 i32))
+                (func (export "bugi@v0_plugin_function_noop") (param i32 i32 i64) (result i64)
+                    i64.const 0)
+                (@custom "bugi@v0_plugin_id" "start-test"))
+            "#
+        ))?;
+
+        // the import fails instead of panicking the host
+        let univ = Universe::new();
+        let pref = univ.add_plugin(WasmPlugin::load_bin(&bin)?)?;
+        match pref.call::<RmpTag, ()>("noop", ()) {
+            Err(BugiError::PluginLoadError(message)) => {
+                assert!(message.contains("outside a plugin call"), "{message}")
+            }
+            res => panic!("unexpected result: {res:?}"),
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,21 @@
-use bugi_wasm_pdk::{call, export, macro_prelude::RmpTag, plugin_id};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use bugi_wasm_pdk::{call, error, export, info, macro_prelude::RmpTag, plugin_id, plugin_manifest};
 
 plugin_id!("wasm-test-plug");
 
+plugin_manifest! {
+    description: "plugin for the bugi tests",
+    authors: ["bugi developers"],
+    dependencies: ["host"],
+    capabilities: [("host", "get_*")],
+    symbols: [
+        fn reverse_string(String) -> String,
+        fn one_zero(String),
+        fn "call_univ_test"() -> String,
+    ],
+}
+
 #[export("reverse_string", RmpTag)]
 fn reverse_string(str: String) -> String {
     str.chars().rev().collect()
@@ -19,6 +33,48 @@ fn zero_one() -> String {
 
 #[export("call_univ_test", RmpTag)]
 fn cuniv() -> String {
-    let res = call::<RmpTag, _>("host", "get_string", ());
-    res
+    call::<RmpTag, _>("host", "get_string", ()).unwrap()
+}
+
+#[export("call_univ_missing", RmpTag)]
+fn call_univ_missing() -> String {
+    match call::<RmpTag, String>("missing", "nothing", ()) {
+        Ok(_) => "unexpected".to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[export("trap", RmpTag)]
+fn trap() {
+    core::arch::wasm32::unreachable()
+}
+
+#[export("panic", RmpTag)]
+fn panic(code: u32) {
+    panic!("panic test: {}", code)
+}
+
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[export("count_up", RmpTag)]
+fn count_up() -> u32 {
+    COUNTER.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[export("spin", RmpTag)]
+#[allow(clippy::empty_loop)]
+fn spin() {
+    loop {}
+}
+
+#[export("alloc_bytes", RmpTag)]
+fn alloc_bytes(len: u32) -> u32 {
+    let bytes = std::hint::black_box(vec![1u8; len as usize]);
+    bytes.len() as u32
+}
+
+#[export("log_test", RmpTag)]
+fn log_test(name: String) {
+    info!("hello, {}", name);
+    error!("bye, {}", name);
 }
@@ -9,3 +9,11 @@ pub fn export(attr: TokenStream, item: TokenStream) -> TokenStream {
 pub fn plugin_id(name: TokenStream) -> TokenStream {
     bugi_wasm_pdk_macro2::plugin_id_macro(name.into()).into()
 }
+
+/// Embed the `bugi@v0_plugin_manifest` section.
+/// The `symbols` list is written by hand and must match the `#[export]` functions:
+/// a declared symbol that isn't exported is rejected when the plugin is loaded.
+#[proc_macro]
+pub fn plugin_manifest(manifest: TokenStream) -> TokenStream {
+    bugi_wasm_pdk_macro2::plugin_manifest_macro(manifest.into()).into()
+}
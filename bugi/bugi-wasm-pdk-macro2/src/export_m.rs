@@ -84,10 +84,10 @@ pub fn export_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
     let fn_name_ident = format_ident!("__bugi_func_{}", &name.value());
     let fn_name_export = format!("{}{}", WASM_SPEC_FUNC, &name.value());
     let token = quote! {
-    #[no_mangle]
     #[export_name = #fn_name_export]
     extern "C" fn #fn_name_ident(arg_ptr: u32, arg_len: u32, abi_type: u64) -> u64 {
         use ::bugi_wasm_pdk::macro_prelude::*;
+        ::bugi_wasm_pdk::set_panic_hook();
         if <#abi_type as SerializeTag>::get_abi_id() != abi_type {
                 panic!("ABI Type(id: {}) is not match this function(id: {})", abi_type, <#abi_type as SerializeTag>::get_abi_id());
             }
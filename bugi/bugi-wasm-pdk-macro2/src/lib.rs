@@ -1,4 +1,6 @@
 pub mod export_m;
 pub mod plugin_id_m;
+pub mod plugin_manifest_m;
 pub use crate::export_m::export_macro;
 pub use crate::plugin_id_m::plugin_id_macro;
+pub use crate::plugin_manifest_m::plugin_manifest_macro;
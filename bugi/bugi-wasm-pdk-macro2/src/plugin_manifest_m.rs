@@ -0,0 +1,164 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use rmpv::Value;
+use syn::{
+    bracketed, parenthesized,
+    parse::{Parse, ParseStream},
+    parse2,
+    punctuated::Punctuated,
+    Ident, LitStr, ReturnType, Token, Type,
+};
+
+const WASM_SPEC_MANIFEST: &str = "bugi@v0_plugin_manifest";
+
+/// A `fn name(Param, ..) -> Result` entry of `symbols`.
+/// The name may be a string, for symbols that aren't identifiers.
+struct Signature {
+    name: String,
+    params: Vec<String>,
+    result: String,
+}
+
+impl Parse for Signature {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<Token![fn]>()?;
+        let name = if input.peek(LitStr) {
+            input.parse::<LitStr>()?.value()
+        } else {
+            input.parse::<Ident>()?.to_string()
+        };
+        let content;
+        parenthesized!(content in input);
+        let params = Punctuated::<Type, Token![,]>::parse_terminated(&content)?
+            .iter()
+            .map(type_name)
+            .collect();
+        let result = match input.parse::<ReturnType>()? {
+            ReturnType::Default => "()".to_string(),
+            ReturnType::Type(_, ty) => type_name(&ty),
+        };
+        Ok(Self {
+            name,
+            params,
+            result,
+        })
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    ty.to_token_stream().to_string().replace(' ', "")
+}
+
+/// Parsed manifest, already in the messagepack layout of the section
+struct Manifest(Vec<(Value, Value)>);
+
+impl Parse for Manifest {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut entries = Vec::new();
+        while !input.is_empty() {
+            let key = input.parse::<Ident>()?;
+            input.parse::<Token![:]>()?;
+            let value = match key.to_string().as_str() {
+                "version" | "description" => Value::from(input.parse::<LitStr>()?.value()),
+                "authors" | "dependencies" => {
+                    let content;
+                    bracketed!(content in input);
+                    Value::Array(
+                        Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?
+                            .iter()
+                            .map(|lit| Value::from(lit.value()))
+                            .collect(),
+                    )
+                }
+                "capabilities" => {
+                    let content;
+                    bracketed!(content in input);
+                    let mut caps = Vec::new();
+                    while !content.is_empty() {
+                        let cap;
+                        parenthesized!(cap in content);
+                        let plugin = cap.parse::<LitStr>()?.value();
+                        cap.parse::<Token![,]>()?;
+                        let symbol = cap.parse::<LitStr>()?.value();
+                        caps.push(Value::Map(vec![
+                            (Value::from("plugin"), Value::from(plugin)),
+                            (Value::from("symbol"), Value::from(symbol)),
+                        ]));
+                        if !content.is_empty() {
+                            content.parse::<Token![,]>()?;
+                        }
+                    }
+                    Value::Array(caps)
+                }
+                "symbols" => {
+                    let content;
+                    bracketed!(content in input);
+                    Value::Array(
+                        Punctuated::<Signature, Token![,]>::parse_terminated(&content)?
+                            .into_iter()
+                            .map(|sig| {
+                                Value::Map(vec![
+                                    (Value::from("name"), Value::from(sig.name)),
+                                    (
+                                        Value::from("params"),
+                                        Value::Array(
+                                            sig.params.into_iter().map(Value::from).collect(),
+                                        ),
+                                    ),
+                                    (Value::from("result"), Value::from(sig.result)),
+                                ])
+                            })
+                            .collect(),
+                    )
+                }
+                _ => return Err(syn::Error::new(key.span(), "unknown manifest key")),
+            };
+            entries.push((Value::from(key.to_string()), value));
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(Self(entries))
+    }
+}
+
+/// Keys left out are taken from the package being built, if it sets them
+fn package_defaults(entries: &mut Vec<(Value, Value)>) {
+    let has = |entries: &Vec<(Value, Value)>, key: &str| {
+        entries.iter().any(|(k, _)| k.as_str() == Some(key))
+    };
+    for (key, var) in [
+        ("version", "CARGO_PKG_VERSION"),
+        ("description", "CARGO_PKG_DESCRIPTION"),
+    ] {
+        match std::env::var(var) {
+            Ok(value) if !value.is_empty() && !has(entries, key) => {
+                entries.push((Value::from(key), Value::from(value)))
+            }
+            _ => {}
+        }
+    }
+    match std::env::var("CARGO_PKG_AUTHORS") {
+        Ok(authors) if !authors.is_empty() && !has(entries, "authors") => entries.push((
+            Value::from("authors"),
+            Value::Array(authors.split(':').map(Value::from).collect()),
+        )),
+        _ => {}
+    }
+}
+
+pub fn plugin_manifest_macro(input: TokenStream) -> TokenStream {
+    let mut manifest = match parse2::<Manifest>(input) {
+        Ok(manifest) => manifest,
+        Err(err) => return err.to_compile_error(),
+    };
+    package_defaults(&mut manifest.0);
+
+    let mut data = Vec::new();
+    rmpv::encode::write_value(&mut data, &Value::Map(manifest.0)).unwrap();
+    let len = data.len();
+    quote! {
+        #[link_section = #WASM_SPEC_MANIFEST]
+        static __BUGI_PLUGIN_MANIFEST: [u8; #len] = [#(#data),*];
+    }
+}
@@ -1,12 +1,19 @@
 use std::alloc::Layout;
+use std::sync::Once;
 
 use bugi_share::FromByte;
 use bugi_share::ParamListTo;
+use bugi_share::SerializeError;
 use bugi_share::SerializeTag;
+use bugi_share::ERROR_ABI_ID;
 use rmpv::Value;
 
+pub use bugi_pdk_share::{debug, error, info, log, trace, warn};
+pub use bugi_share::LogLevel;
+
 pub use bugi_wasm_pdk_macro::export;
 pub use bugi_wasm_pdk_macro::plugin_id;
+pub use bugi_wasm_pdk_macro::plugin_manifest;
 
 pub mod macro_prelude {
     pub use bugi_share::*;
@@ -16,13 +23,60 @@ pub mod macro_prelude {
 #[allow(improper_ctypes)]
 extern "C" {
     fn call_univ(arg_ptr: u32, arg_len: u32) -> u64;
+
+    #[link_name = "log"]
+    fn host_log(level: u32, msg_ptr: u32, msg_len: u32);
+
+    #[link_name = "panic"]
+    fn host_panic(msg_ptr: u32, msg_len: u32, loc_ptr: u32, loc_len: u32);
+}
+
+/// Report panics to the host before trapping, and point the log macros at the host.
+/// Every `#[export]` function calls this, so plugins don't need to.
+pub fn set_panic_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        bugi_pdk_share::set_log_sink(log);
+        std::panic::set_hook(Box::new(|info| {
+            let message = bugi_pdk_share::panic_message(info.payload());
+            let location = info
+                .location()
+                .map(|location| location.to_string())
+                .unwrap_or_default();
+            unsafe {
+                host_panic(
+                    message.as_ptr() as u32,
+                    message.len() as u32,
+                    location.as_ptr() as u32,
+                    location.len() as u32,
+                )
+            }
+        }))
+    });
+}
+
+/// Write a log record to the host's log sink
+pub fn log(level: LogLevel, message: &str) {
+    unsafe { host_log(level as u32, message.as_ptr() as u32, message.len() as u32) }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CallError {
+    #[error("cannot serialize: {0}")]
+    CannotSerialize(#[from] SerializeError),
+
+    #[error("call_univ error: {0}")]
+    Univ(String),
+
+    #[error("call_univ returned an invalid result")]
+    InvalidResult,
 }
 
 pub fn call<SType: SerializeTag, Output: FromByte<SType>>(
     id: &str,
     symbol: &str,
     param: impl ParamListTo<SType>,
-) -> Output {
+) -> Result<Output, CallError> {
     let argv = Value::Map(vec![
         (Value::String("id".into()), Value::String(id.into())),
         (Value::String("name".into()), Value::String(symbol.into())),
@@ -32,7 +86,7 @@ pub fn call<SType: SerializeTag, Output: FromByte<SType>>(
         ),
         (
             Value::String("detail".into()),
-            Value::Binary(param.to_byte().unwrap()),
+            Value::Binary(param.to_byte()?),
         ),
     ]);
 
@@ -52,10 +106,30 @@ pub fn call<SType: SerializeTag, Output: FromByte<SType>>(
 
     dealloc(res_ptr, res_len);
 
-    Output::from_byte(&res).unwrap()
+    let (abi, detail) = decode_result(&res).ok_or(CallError::InvalidResult)?;
+    if abi == ERROR_ABI_ID {
+        return Err(CallError::Univ(
+            String::from_utf8_lossy(&detail).into_owned(),
+        ));
+    }
+
+    Ok(Output::from_byte(&detail)?)
+}
+
+/// RESULT Type of `call_univ`: (abi, detail)
+fn decode_result(bin: &[u8]) -> Option<(u64, Vec<u8>)> {
+    let value = rmpv::decode::read_value(&mut &bin[..]).ok()?;
+    let (mut abi, mut detail) = (None, None);
+    for (name, value) in value.as_map()? {
+        match name.as_str()? {
+            "abi" => abi = value.as_u64(),
+            "detail" => detail = value.as_slice().map(|d| d.to_vec()),
+            _ => {}
+        }
+    }
+    Some((abi?, detail?))
 }
 
-#[no_mangle]
 #[export_name = "bugi@v0_low_malloc"]
 pub extern "C" fn alloc(len: u32) -> u32 {
     let layout = Layout::array::<u8>(len as usize).unwrap();
@@ -67,7 +141,6 @@ pub extern "C" fn alloc(len: u32) -> u32 {
     }
 }
 
-#[no_mangle]
 #[export_name = "bugi@v0_low_free"]
 pub extern "C" fn dealloc(ptr: u32, len: u32) {
     let layout = Layout::array::<u8>(len as usize).unwrap();
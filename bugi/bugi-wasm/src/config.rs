@@ -0,0 +1,75 @@
+/// Size of a wasm page
+const PAGE_SIZE: u64 = 0x10000;
+
+/// Resource limits of a wasm plugin
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WasmPluginConfig {
+    /// maximum size of a linear memory, in 64KiB pages
+    pub max_memory_pages: Option<u64>,
+    /// maximum number of tables
+    pub max_tables: Option<usize>,
+    /// maximum number of instances
+    pub max_instances: Option<usize>,
+}
+
+/// `ResourceLimiter` of the plugin's store
+#[derive(Default)]
+pub(crate) struct WasmLimiter {
+    config: WasmPluginConfig,
+    /// why the last growth was refused
+    pub exceeded: Option<String>,
+}
+
+impl WasmLimiter {
+    pub fn new(config: WasmPluginConfig) -> Self {
+        Self {
+            config,
+            exceeded: None,
+        }
+    }
+}
+
+impl wasmtime::ResourceLimiter for WasmLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        if let Some(pages) = self.config.max_memory_pages {
+            if desired as u64 > pages * PAGE_SIZE {
+                self.exceeded = Some(format!(
+                    "memory can't grow to {} bytes (max: {} pages)",
+                    desired, pages
+                ));
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
+    fn instances(&self) -> usize {
+        self.config
+            .max_instances
+            .unwrap_or(wasmtime::DEFAULT_INSTANCE_LIMIT)
+    }
+
+    fn tables(&self) -> usize {
+        self.config
+            .max_tables
+            .unwrap_or(wasmtime::DEFAULT_TABLE_LIMIT)
+    }
+
+    fn memories(&self) -> usize {
+        wasmtime::DEFAULT_MEMORY_LIMIT
+    }
+}
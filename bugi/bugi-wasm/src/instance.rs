@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+
+use bugi_core::{BugiError, EnvPloxy};
+use wasmtime::{Memory, TypedFunc};
+
+use crate::{
+    config::WasmLimiter,
+    limits::{CallBudget, NO_DEADLINE},
+    SPEC_LOW_FREE, SPEC_LOW_MALLOC, SPEC_PLUGIN_FUNC,
+};
+
+/// Data stored in the plugin's wasmtime store
+#[derive(Default)]
+pub(crate) struct WasmState {
+    /// ploxy of the running call
+    pub ploxy: Option<EnvPloxy>,
+
+    /// plugin ID and symbol of the running call
+    pub caller: (String, String),
+
+    /// message and location of the guest's panic in the running call
+    pub panic: Option<(String, String)>,
+
+    /// cached `bugi@v0_low_malloc`
+    pub malloc: Option<TypedFunc<(u32,), u32>>,
+
+    /// cached `bugi@v0_low_free`
+    pub free: Option<TypedFunc<(u32, u32), ()>>,
+
+    /// cached `memory`
+    pub memory: Option<Memory>,
+
+    pub limiter: WasmLimiter,
+
+    /// whether the engine meters fuel
+    pub fuel: bool,
+
+    /// whether the engine checks epoch deadlines
+    pub epoch_interruption: bool,
+
+    /// WASI context, set up before instantiation
+    #[cfg(feature = "wasi")]
+    pub wasi: Option<wasi_common::WasiCtx>,
+
+    /// `WasiOutput::Log` streams of the WASI context
+    #[cfg(feature = "wasi")]
+    pub wasi_logs: crate::wasi::WasiLogs,
+}
+
+/// Error of a host import called while no plugin call runs, e.g. from a start function
+pub(crate) fn outside_call() -> anyhow::Error {
+    anyhow::anyhow!("host import called outside a plugin call")
+}
+
+pub(crate) type PluginFunc = TypedFunc<(u32, u32, u64), u64>;
+
+/// A long-lived instance of a wasm plugin
+pub(crate) struct WasmInstance {
+    store: wasmtime::Store<WasmState>,
+    instance: wasmtime::Instance,
+    funcs: HashMap<String, PluginFunc>,
+}
+
+impl WasmInstance {
+    pub fn new(
+        engine: &wasmtime::Engine,
+        linker: &wasmtime::Linker<WasmState>,
+        module: &wasmtime::Module,
+        state: WasmState,
+        caller: (&str, &str),
+    ) -> Result<Self, BugiError> {
+        let mut store = Self::store(engine, state);
+        let instance = linker.instantiate(&mut store, module);
+        Self::finish(store, instance, caller)
+    }
+
+    /// `new` for a runtime with async support
+    pub async fn new_async(
+        engine: &wasmtime::Engine,
+        linker: &wasmtime::Linker<WasmState>,
+        module: &wasmtime::Module,
+        state: WasmState,
+        caller: (&str, &str),
+    ) -> Result<Self, BugiError> {
+        let mut store = Self::store(engine, state);
+        let instance = linker.instantiate_async(&mut store, module).await;
+        Self::finish(store, instance, caller)
+    }
+
+    fn store(engine: &wasmtime::Engine, state: WasmState) -> wasmtime::Store<WasmState> {
+        let mut store = wasmtime::Store::new(engine, state);
+        store.limiter(|state| &mut state.limiter);
+        if store.data().fuel {
+            store.set_fuel(u64::MAX).unwrap();
+        }
+        if store.data().epoch_interruption {
+            store.set_epoch_deadline(NO_DEADLINE);
+        }
+        store
+    }
+
+    fn finish(
+        mut store: wasmtime::Store<WasmState>,
+        instance: anyhow::Result<wasmtime::Instance>,
+        (id, symbol): (&str, &str),
+    ) -> Result<Self, BugiError> {
+        let instance = instance.map_err(|err| match store.data_mut().limiter.exceeded.take() {
+            Some(message) => BugiError::ResourceLimitExceeded {
+                id: id.to_string(),
+                symbol: symbol.to_string(),
+                message,
+            },
+            None => BugiError::PluginLoadError(format!("Failed to wasm instantiate: {:?}", err)),
+        })?;
+
+        let malloc = instance
+            .get_typed_func::<(u32,), u32>(&mut store, SPEC_LOW_MALLOC)
+            .map_err(|err| {
+                BugiError::PluginCallError(format!("{SPEC_LOW_MALLOC} get error: {err}"))
+            })?;
+
+        let free = instance
+            .get_typed_func::<(u32, u32), ()>(&mut store, SPEC_LOW_FREE)
+            .map_err(|err| {
+                BugiError::PluginCallError(format!("{SPEC_LOW_FREE} get error: {err}"))
+            })?;
+
+        let memory = instance.get_memory(&mut store, "memory").ok_or_else(|| {
+            BugiError::PluginCallError("memory get error: `memory` is not exported".to_string())
+        })?;
+
+        let state = store.data_mut();
+        state.malloc = Some(malloc);
+        state.free = Some(free);
+        state.memory = Some(memory);
+
+        Ok(Self {
+            store,
+            instance,
+            funcs: HashMap::new(),
+        })
+    }
+
+    /// Get (and cache) a plugin function
+    pub fn func(&mut self, symbol: &str) -> Result<PluginFunc, BugiError> {
+        if let Some(func) = self.funcs.get(symbol) {
+            return Ok(func.clone());
+        }
+
+        let func = self
+            .instance
+            .get_typed_func::<(u32, u32, u64), u64>(
+                &mut self.store,
+                &format!("{}{}", SPEC_PLUGIN_FUNC, symbol),
+            )
+            .map_err(|err| {
+                BugiError::PluginCallError(format!("Symbol get error({}): {}", symbol, err))
+            })?;
+
+        self.funcs.insert(symbol.to_string(), func.clone());
+        Ok(func)
+    }
+
+    /// Run a plugin function within `budget`. Returns the result and the unused fuel.
+    /// If this returns an error, the instance may be broken and should not be reused.
+    #[allow(clippy::too_many_arguments)]
+    pub fn invoke(
+        &mut self,
+        id: &str,
+        symbol: &str,
+        func: &PluginFunc,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+        budget: &CallBudget,
+    ) -> (Result<Vec<u8>, BugiError>, u64) {
+        self.begin(id, symbol, ploxy, budget);
+        let res = self.invoke_inner(id, symbol, func, param, abi, budget);
+        self.end(res)
+    }
+
+    /// `invoke` for a runtime with async support
+    #[allow(clippy::too_many_arguments)]
+    pub async fn invoke_async(
+        &mut self,
+        id: &str,
+        symbol: &str,
+        func: &PluginFunc,
+        param: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+        budget: &CallBudget,
+    ) -> (Result<Vec<u8>, BugiError>, u64) {
+        self.begin(id, symbol, ploxy, budget);
+        let res = self
+            .invoke_inner_async(id, symbol, func, param, abi, budget)
+            .await;
+        self.end(res)
+    }
+
+    fn begin(&mut self, id: &str, symbol: &str, ploxy: EnvPloxy, budget: &CallBudget) {
+        #[cfg(feature = "wasi")]
+        self.store.data().wasi_logs.begin(&ploxy, id, symbol);
+        self.store.data_mut().ploxy = Some(ploxy);
+        self.store.data_mut().caller = (id.to_string(), symbol.to_string());
+        self.store.data_mut().limiter.exceeded = None;
+        self.store.data_mut().panic = None;
+        if self.store.data().fuel {
+            self.store.set_fuel(budget.fuel).unwrap();
+        }
+        if self.store.data().epoch_interruption {
+            self.store.set_epoch_deadline(budget.epoch_ticks);
+        }
+    }
+
+    fn end(&mut self, res: Result<Vec<u8>, BugiError>) -> (Result<Vec<u8>, BugiError>, u64) {
+        let res = match res {
+            // the guest reported a panic before trapping
+            Err(BugiError::PluginTrap { id, symbol, .. }) if self.store.data().panic.is_some() => {
+                let (message, location) = self.store.data_mut().panic.take().unwrap();
+                Err(BugiError::PluginPanicked {
+                    id,
+                    symbol,
+                    message,
+                    location,
+                })
+            }
+            // the guest trapped because it ran out of memory
+            Err(BugiError::PluginTrap { id, symbol, .. })
+                if self.store.data().limiter.exceeded.is_some() =>
+            {
+                Err(BugiError::ResourceLimitExceeded {
+                    id,
+                    symbol,
+                    message: self.store.data_mut().limiter.exceeded.take().unwrap(),
+                })
+            }
+            res => res,
+        };
+
+        #[cfg(feature = "wasi")]
+        self.store.data().wasi_logs.end();
+        self.store.data_mut().ploxy = None;
+        let mut fuel_left = 0;
+        if self.store.data().fuel {
+            fuel_left = self.store.get_fuel().unwrap_or(0);
+            self.store.set_fuel(u64::MAX).unwrap();
+        }
+        if self.store.data().epoch_interruption {
+            self.store.set_epoch_deadline(NO_DEADLINE);
+        }
+        (res, fuel_left)
+    }
+
+    fn invoke_inner(
+        &mut self,
+        id: &str,
+        symbol: &str,
+        func: &PluginFunc,
+        param: &[u8],
+        abi: u64,
+        budget: &CallBudget,
+    ) -> Result<Vec<u8>, BugiError> {
+        let state = self.store.data();
+        let (malloc, free, memory) = (
+            state.malloc.clone().unwrap(),
+            state.free.clone().unwrap(),
+            state.memory.unwrap(),
+        );
+
+        let trap = trap(id, symbol, budget);
+
+        let mem_ptr = malloc
+            .call(&mut self.store, (param.len() as u32,))
+            .map_err(trap)?;
+
+        if let Err(err) = memory.write(&mut self.store, mem_ptr as usize, param) {
+            return Err(BugiError::PluginCallError(format!(
+                "can't write memory: {err}"
+            )));
+        }
+
+        let res = func
+            .call(&mut self.store, (mem_ptr, param.len() as u32, abi))
+            .map_err(trap)?;
+
+        let res_ptr = (res >> 32) as u32;
+        let res_len = (res & 0xFFFFFFFF) as u32;
+
+        let mut res = vec![0; res_len as usize];
+        if let Err(err) = memory.read(&self.store, res_ptr as usize, &mut res) {
+            return Err(BugiError::PluginCallError(format!(
+                "can't read memory: {err}"
+            )));
+        }
+
+        free.call(&mut self.store, (res_ptr, res_len))
+            .map_err(trap)?;
+
+        Ok(res)
+    }
+
+    async fn invoke_inner_async(
+        &mut self,
+        id: &str,
+        symbol: &str,
+        func: &PluginFunc,
+        param: &[u8],
+        abi: u64,
+        budget: &CallBudget,
+    ) -> Result<Vec<u8>, BugiError> {
+        let state = self.store.data();
+        let (malloc, free, memory) = (
+            state.malloc.clone().unwrap(),
+            state.free.clone().unwrap(),
+            state.memory.unwrap(),
+        );
+
+        let trap = trap(id, symbol, budget);
+
+        let mem_ptr = malloc
+            .call_async(&mut self.store, (param.len() as u32,))
+            .await
+            .map_err(trap)?;
+
+        if let Err(err) = memory.write(&mut self.store, mem_ptr as usize, param) {
+            return Err(BugiError::PluginCallError(format!(
+                "can't write memory: {err}"
+            )));
+        }
+
+        let res = func
+            .call_async(&mut self.store, (mem_ptr, param.len() as u32, abi))
+            .await
+            .map_err(trap)?;
+
+        let res_ptr = (res >> 32) as u32;
+        let res_len = (res & 0xFFFFFFFF) as u32;
+
+        let mut res = vec![0; res_len as usize];
+        if let Err(err) = memory.read(&self.store, res_ptr as usize, &mut res) {
+            return Err(BugiError::PluginCallError(format!(
+                "can't read memory: {err}"
+            )));
+        }
+
+        free.call_async(&mut self.store, (res_ptr, res_len))
+            .await
+            .map_err(trap)?;
+
+        Ok(res)
+    }
+}
+
+/// Maps a trap of a call to its error
+fn trap<'a>(
+    id: &'a str,
+    symbol: &'a str,
+    budget: &'a CallBudget,
+) -> impl Fn(anyhow::Error) -> BugiError + Copy + 'a {
+    move |err| match budget.exceeded(&err) {
+        Some(limit) => BugiError::ExecutionLimitExceeded {
+            id: id.to_string(),
+            symbol: symbol.to_string(),
+            limit,
+        },
+        None => BugiError::PluginTrap {
+            id: id.to_string(),
+            symbol: symbol.to_string(),
+            message: format!("{err:#}"),
+        },
+    }
+}
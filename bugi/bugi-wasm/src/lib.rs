@@ -1,65 +1,374 @@
-use core::panic;
-use std::{collections::HashMap, sync::LazyLock};
-
-use rmpv::ValueRef;
-use wasmtime::Caller;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use instance::{WasmInstance, WasmState};
+use limits::ExecLimits;
+use runtime::DEFAULT_RUNTIME;
+
+pub use config::WasmPluginConfig;
+pub use runtime::{InstanceAllocation, OptLevel, WasmRuntime, WasmRuntimeBuilder};
+pub use validate::SpecViolations;
+#[cfg(feature = "wasi")]
+pub use wasi::{WasiCapture, WasiConfig, WasiDir, WasiOutput};
+
+mod config;
+mod instance;
+mod limits;
+mod log;
+mod manifest;
+mod runtime;
+mod univ;
+mod validate;
+#[cfg(feature = "wasi")]
+mod wasi;
 
 const SPEC_CALL_UNIV: (&str, &str) = ("bugi@v0", "call_univ");
+const SPEC_LOG: (&str, &str) = ("bugi@v0", "log");
+const SPEC_PANIC: (&str, &str) = ("bugi@v0", "panic");
 const SPEC_PLUGIN_FUNC: &str = "bugi@v0_plugin_function_";
 const SPEC_LOW_MALLOC: &str = "bugi@v0_low_malloc";
 const SPEC_LOW_FREE: &str = "bugi@v0_low_free";
 const SPEC_PLUG_ID: &str = "bugi@v0_plugin_id";
+const SPEC_PLUG_MANIFEST: &str = "bugi@v0_plugin_manifest";
 
-static ENGINE: LazyLock<wasmtime::Engine> = LazyLock::new(|| {
-    let mut config = wasmtime::Config::new();
-    config.allocation_strategy(wasmtime::InstanceAllocationStrategy::pooling());
-    wasmtime::Engine::new(&config).unwrap()
-});
-
+/// A plugin running on wasmtime.
+/// The instance is kept between calls, so guest state survives until `reset`.
+/// Calls made while the instance is busy (concurrent or re-entrant) run on a temporary instance.
 pub struct WasmPlugin {
-    section: HashMap<String, Vec<u8>>,
+    runtime: WasmRuntime,
+    str_id: String,
+    metadata: bugi_core::PluginMetadata,
     module: wasmtime::Module,
+    linker: wasmtime::Linker<WasmState>,
+    slot: Mutex<InstanceSlot>,
+    limits: ExecLimits,
+    config: WasmPluginConfig,
+    #[cfg(feature = "wasi")]
+    wasi: wasi::WasiSetup,
+}
+
+/// The persistent instance of a plugin, shared between calls
+#[derive(Default)]
+struct InstanceSlot {
+    instance: Option<WasmInstance>,
+    /// the instance is checked out by a running call
+    busy: bool,
+    /// bumped by `reset` so that running calls don't bring back an old instance
+    generation: u64,
+    /// fuel left until `reset`, if the total fuel is limited
+    fuel_left: Option<u64>,
+}
+
+/// How a call got its instance
+enum Lease {
+    /// the persistent instance, from the given generation
+    Persistent(u64),
+    /// a throwaway instance, used while the persistent one is busy
+    Temporary,
 }
 
-fn parse_custom_section(bin: &[u8]) -> HashMap<String, Vec<u8>> {
+/// Custom sections of a module, by name.
+/// Fails if the module is malformed.
+fn parse_custom_section(bin: &[u8]) -> Result<HashMap<String, Vec<u8>>, bugi_core::BugiError> {
     let parser = wasmparser::Parser::new(0);
-    let parsed_data = parser.parse_all(bin);
     let mut res = HashMap::new();
 
-    for payload in parsed_data {
-        match payload {
-            Ok(wasmparser::Payload::CustomSection(sec)) => {
-                res.insert(sec.name().to_string(), sec.data().to_vec());
-            }
-            Ok(_) => {}
-            Err(e) => {
-                println!("Error: {:?}", e);
-            }
+    for payload in parser.parse_all(bin) {
+        let payload = payload.map_err(|err| {
+            bugi_core::BugiError::PluginLoadError(format!("malformed wasm module: {err}"))
+        })?;
+        if let wasmparser::Payload::CustomSection(sec) = payload {
+            res.insert(sec.name().to_string(), sec.data().to_vec());
         }
     }
 
-    res
+    Ok(res)
 }
 
 impl WasmPlugin {
+    /// Load with the default runtime
     pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
-        let engine = ENGINE.clone();
-        let section = parse_custom_section(&std::fs::read(path.as_ref())?);
-        let module = wasmtime::Module::from_file(&engine, path)?;
-        Ok(Self { module, section })
+        DEFAULT_RUNTIME.load(path)
     }
 
+    /// Load with the default runtime
     pub fn load_bin(bin: &[u8]) -> anyhow::Result<Self> {
-        let engine = ENGINE.clone();
-        let section = parse_custom_section(bin);
-        let module = wasmtime::Module::new(&engine, bin)?;
-        Ok(Self { module, section })
+        DEFAULT_RUNTIME.load_bin(bin)
+    }
+
+    /// Compile a module for the default runtime configuration into `cache_dir`.
+    /// A `WasmRuntime` built with the same `cache_dir` loads it without codegen.
+    pub fn precompile(
+        path: impl AsRef<std::path::Path>,
+        cache_dir: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<std::path::PathBuf> {
+        runtime::precompile_into(
+            DEFAULT_RUNTIME.engine(),
+            &std::fs::read(path)?,
+            cache_dir.as_ref(),
+        )
+    }
+
+    pub(crate) fn new(
+        runtime: WasmRuntime,
+        module: wasmtime::Module,
+        section: HashMap<String, Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        let (str_id, metadata) = validate::validate(&module, &section)?;
+
+        let mut linker = wasmtime::Linker::new(runtime.engine());
+        if runtime.async_support() {
+            linker.func_wrap_async(SPEC_CALL_UNIV.0, SPEC_CALL_UNIV.1, univ::call_univ_async)?;
+        } else {
+            linker.func_wrap(SPEC_CALL_UNIV.0, SPEC_CALL_UNIV.1, univ::call_univ)?;
+        }
+        linker.func_wrap(SPEC_LOG.0, SPEC_LOG.1, log::log)?;
+        linker.func_wrap(SPEC_PANIC.0, SPEC_PANIC.1, log::panic)?;
+        #[cfg(feature = "wasi")]
+        wasi_common::sync::add_to_linker(&mut linker, |state: &mut WasmState| {
+            state.wasi.as_mut().unwrap()
+        })?;
+
+        Ok(Self {
+            runtime,
+            str_id,
+            metadata,
+            module,
+            linker,
+            slot: Mutex::new(InstanceSlot::default()),
+            limits: ExecLimits::default(),
+            config: WasmPluginConfig::default(),
+            #[cfg(feature = "wasi")]
+            wasi: wasi::WasiSetup::default(),
+        })
+    }
+
+    /// Set the resource limits. The persistent instance is dropped.
+    pub fn set_config(&mut self, config: WasmPluginConfig) {
+        self.config = config;
+        self.reset();
+    }
+
+    /// Set what the plugin may touch through WASI. The persistent instance is dropped.
+    /// Fails if a preopened directory can't be opened.
+    #[cfg(feature = "wasi")]
+    pub fn set_wasi(&mut self, config: WasiConfig) -> anyhow::Result<()> {
+        self.wasi = wasi::WasiSetup::new(config)?;
+        self.reset();
+        Ok(())
+    }
+
+    /// Handle of the captured stdout, if it is `WasiOutput::Capture`
+    #[cfg(feature = "wasi")]
+    pub fn wasi_stdout(&self) -> Option<WasiCapture> {
+        self.wasi.stdout.clone()
+    }
+
+    /// Handle of the captured stderr, if it is `WasiOutput::Capture`
+    #[cfg(feature = "wasi")]
+    pub fn wasi_stderr(&self) -> Option<WasiCapture> {
+        self.wasi.stderr.clone()
+    }
+
+    /// Limit the fuel a single call may consume.
+    /// Fails if the runtime was built without fuel.
+    pub fn set_fuel_per_call(&mut self, fuel: Option<u64>) -> anyhow::Result<()> {
+        self.require_fuel(fuel)?;
+        self.limits.fuel_per_call = fuel;
+        Ok(())
+    }
+
+    /// Limit the fuel all calls may consume until `reset`.
+    /// Fails if the runtime was built without fuel.
+    pub fn set_fuel_total(&mut self, fuel: Option<u64>) -> anyhow::Result<()> {
+        self.require_fuel(fuel)?;
+        self.limits.fuel_total = fuel;
+        self.slot.get_mut().unwrap().fuel_left = fuel;
+        Ok(())
+    }
+
+    /// Limit the wall-clock time of a single call.
+    /// The deadline is checked every 10ms.
+    /// Fails if the runtime was built without epoch interruption.
+    pub fn set_deadline(&mut self, deadline: Option<Duration>) -> anyhow::Result<()> {
+        if deadline.is_some() {
+            if !self.runtime.epoch_interruption() {
+                anyhow::bail!("deadlines need a runtime built with epoch interruption");
+            }
+            self.runtime.start_epoch_ticker();
+        }
+        self.limits.deadline = deadline;
+        Ok(())
+    }
+
+    fn require_fuel(&self, fuel: Option<u64>) -> anyhow::Result<()> {
+        if fuel.is_some() && !self.runtime.fuel() {
+            anyhow::bail!("fuel limits need a runtime built with fuel");
+        }
+        Ok(())
+    }
+
+    /// The plugin ID embedded in the `bugi@v0_plugin_id` custom section
+    pub fn plugin_id(&self) -> &str {
+        &self.str_id
+    }
+
+    /// Drop the persistent instance.
+    /// The next call starts from a freshly instantiated module.
+    pub fn reset(&self) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.instance = None;
+        slot.generation += 1;
+        slot.fuel_left = self.limits.fuel_total;
+    }
+
+    /// Budget of a call, out of the total fuel left.
+    /// Nothing is taken from the total until `charge`, so overlapping calls
+    /// (re-entrant or from other threads) each get what is left when they start.
+    fn budget(&self) -> limits::CallBudget {
+        let slot = self.slot.lock().unwrap();
+        self.limits.budget(slot.fuel_left)
+    }
+
+    /// Take the fuel a call consumed from the total
+    fn charge(&self, budget: &limits::CallBudget, fuel_left: u64) {
+        let mut slot = self.slot.lock().unwrap();
+        if let Some(left) = slot.fuel_left.as_mut() {
+            *left = left.saturating_sub(budget.fuel.saturating_sub(fuel_left));
+        }
+    }
+
+    /// Store data of a new instance
+    fn state(&self) -> Result<WasmState, bugi_core::BugiError> {
+        #[cfg(feature = "wasi")]
+        let (wasi, wasi_logs) = self.wasi.ctx().map_err(|err| {
+            bugi_core::BugiError::PluginCallError(format!("can't set up WASI: {err}"))
+        })?;
+        Ok(WasmState {
+            limiter: config::WasmLimiter::new(self.config),
+            fuel: self.runtime.fuel(),
+            epoch_interruption: self.runtime.epoch_interruption(),
+            #[cfg(feature = "wasi")]
+            wasi: Some(wasi),
+            #[cfg(feature = "wasi")]
+            wasi_logs,
+            ..Default::default()
+        })
+    }
+
+    /// Take the persistent instance, if it isn't busy.
+    /// While it is busy (concurrent or re-entrant calls), a temporary instance is used instead.
+    fn lease(&self) -> (Option<WasmInstance>, Lease) {
+        let mut slot = self.slot.lock().unwrap();
+        if slot.busy {
+            (None, Lease::Temporary)
+        } else {
+            slot.busy = true;
+            (slot.instance.take(), Lease::Persistent(slot.generation))
+        }
+    }
+
+    /// Take the persistent instance, instantiating it if needed
+    fn checkout(&self, symbol: &str) -> Result<(WasmInstance, Lease), bugi_core::BugiError> {
+        let (instance, lease) = self.lease();
+        if let Some(instance) = instance {
+            return Ok((instance, lease));
+        }
+
+        match self.state().and_then(|state| {
+            WasmInstance::new(
+                self.runtime.engine(),
+                &self.linker,
+                &self.module,
+                state,
+                (&self.str_id, symbol),
+            )
+        }) {
+            Ok(instance) => Ok((instance, lease)),
+            Err(err) => {
+                self.checkin(None, lease);
+                Err(err)
+            }
+        }
+    }
+
+    /// `checkout` for a runtime with async support.
+    /// The lease is given back as broken if the call is dropped before `Checkout::checkin`.
+    async fn checkout_async(
+        &self,
+        symbol: &str,
+    ) -> Result<(WasmInstance, Checkout<'_>), bugi_core::BugiError> {
+        let (instance, lease) = self.lease();
+        let checkout = Checkout {
+            plugin: self,
+            lease: Some(lease),
+        };
+        if let Some(instance) = instance {
+            return Ok((instance, checkout));
+        }
+
+        let instance = WasmInstance::new_async(
+            self.runtime.engine(),
+            &self.linker,
+            &self.module,
+            self.state()?,
+            (&self.str_id, symbol),
+        )
+        .await?;
+        Ok((instance, checkout))
+    }
+
+    /// Give back the instance taken by `checkout`.
+    /// `None` means the instance is broken and must be discarded.
+    fn checkin(&self, instance: Option<WasmInstance>, lease: Lease) {
+        if let Lease::Persistent(generation) = lease {
+            let mut slot = self.slot.lock().unwrap();
+            slot.busy = false;
+            if slot.generation == generation {
+                slot.instance = instance;
+            }
+        }
+    }
+}
+
+/// A lease of an async call, given back even if the call is dropped
+struct Checkout<'a> {
+    plugin: &'a WasmPlugin,
+    lease: Option<Lease>,
+}
+
+impl Checkout<'_> {
+    fn checkin(mut self, instance: Option<WasmInstance>) {
+        self.plugin.checkin(instance, self.lease.take().unwrap());
+    }
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        if let Some(lease) = self.lease.take() {
+            self.plugin.checkin(None, lease);
+        }
     }
 }
 
 impl bugi_core::PluginSystem for WasmPlugin {
     fn str_id(&self) -> String {
-        String::from_utf8(self.section.get(SPEC_PLUG_ID).unwrap().to_vec()).unwrap()
+        self.str_id.clone()
+    }
+
+    fn symbols(&self) -> Vec<bugi_core::PluginSymbol> {
+        // the ABI is chosen by the guest for each call
+        self.module
+            .exports()
+            .filter_map(|export| export.name().strip_prefix(SPEC_PLUGIN_FUNC))
+            .map(|name| bugi_core::PluginSymbol {
+                name: name.to_string(),
+                abi: None,
+            })
+            .collect()
+    }
+
+    fn metadata(&self) -> bugi_core::PluginMetadata {
+        self.metadata.clone()
     }
 
     fn raw_call(
@@ -69,225 +378,92 @@ impl bugi_core::PluginSystem for WasmPlugin {
         abi: u64,
         ploxy: bugi_core::EnvPloxy,
     ) -> Result<Vec<u8>, bugi_core::BugiError> {
-        const STORE_KEY: &str = "WasmPlugin-Store";
+        #[cfg(feature = "tracing")]
+        let _span = bugi_core::CallSpan::enter(tracing::debug_span!(
+            "wasm_call",
+            plugin = self.str_id,
+            symbol,
+            abi,
+            payload = param.len(),
+            duration_us = tracing::field::Empty,
+        ));
+
+        // wasmtime refuses synchronous calls on an async engine
+        if self.runtime.async_support() {
+            return bugi_core::block_on(self.raw_call_async(symbol, param, abi, ploxy));
+        }
 
-        let engine = ENGINE.clone();
+        let (mut instance, lease) = self.checkout(symbol)?;
 
-        let mut store = match ploxy.get_global(STORE_KEY) {
-            Some(store) => store.downcast().unwrap(),
-            None => Box::new(wasmtime::Store::new(&engine, ())),
+        let func = match instance.func(symbol) {
+            Ok(func) => func,
+            Err(err) => {
+                self.checkin(Some(instance), lease);
+                return Err(err);
+            }
         };
 
-        let mut linker = wasmtime::Linker::new(&engine);
-        let ploxy_c = ploxy.clone();
-        linker
-            .func_wrap(
-                SPEC_CALL_UNIV.0,
-                SPEC_CALL_UNIV.1,
-                move |mut caller: Caller<'_, ()>, arg_ptr: u32, arg_len: u32| {
-                    let malloc = caller
-                        .get_export(SPEC_LOW_MALLOC)
-                        .unwrap()
-                        .into_func()
-                        .unwrap()
-                        .typed::<(u32,), u32>(&caller)
-                        .unwrap();
-
-                    let free = caller
-                        .get_export(SPEC_LOW_FREE)
-                        .unwrap()
-                        .into_func()
-                        .unwrap()
-                        .typed::<(u32, u32), ()>(&caller)
-                        .unwrap();
-
-                    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
-
-                    let mut arg = vec![0; arg_len as usize];
-                    if let Err(err) = memory.read(&caller, arg_ptr as usize, &mut arg) {
-                        let err = format!("Can't Read Memory: \n{}", err);
-                        panic!("<Bugi-Wasm> Found Error: {}", &err);
-                    }
-
-                    if let Err(err) = free.call(&mut caller, (arg_ptr, arg_len)) {
-                        panic!("<Bugi-Wasm> Can't Dealloc Memory: {}", err);
-                    }
-
-                    let arg = rmpv::decode::read_value_ref(&mut arg.as_slice()).unwrap();
-
-                    #[derive(Default)]
-                    struct Arg {
-                        id: String,
-                        name: String,
-                        abi: u64,
-                        detail: Vec<u8>,
-                    }
-
-                    let arg = {
-                        let mut a = Arg::default();
-                        if let ValueRef::Map(vec) = arg {
-                            for (name, value) in vec {
-                                if let ValueRef::String(str) = name {
-                                    fn get_string(v: ValueRef) -> String {
-                                        if let ValueRef::String(str) = v {
-                                            str.into_string().unwrap()
-                                        } else {
-                                            panic!(
-                                                "<Bugi-Wasm> `call_univ`'s arg is not satisfiled."
-                                            )
-                                        }
-                                    }
-                                    fn get_u64(v: ValueRef) -> u64 {
-                                        if let ValueRef::Integer(int) = v {
-                                            int.as_u64().unwrap()
-                                        } else {
-                                            panic!(
-                                                "<Bugi-Wasm> `call_univ`'s arg is not satisfiled."
-                                            )
-                                        }
-                                    }
-                                    fn get_bin(v: ValueRef) -> Vec<u8> {
-                                        if let ValueRef::Binary(bin) = v {
-                                            bin.to_vec()
-                                        } else {
-                                            panic!(
-                                                "<Bugi-Wasm> `call_univ`'s arg is not satisfiled."
-                                            )
-                                        }
-                                    }
-                                    match str.as_str().unwrap() {
-                                        "id" => {
-                                            a.id = get_string(value);
-                                        }
-
-                                        "name" => {
-                                            a.name = get_string(value);
-                                        }
-
-                                        "abi" => {
-                                            a.abi = get_u64(value);
-                                        }
-
-                                        "detail" => {
-                                            a.detail = get_bin(value);
-                                        }
-
-                                        _ => {}
-                                    }
-                                }
-                            }
-                        } else {
-                            panic!("<Bugi-Wasm> `call_univ`'s arg is not map");
-                        }
-
-                        a
-                    };
-
-                    let result = ploxy_c.call_univ_raw(&arg.id, &arg.name, &arg.detail, arg.abi);
-
-                    let res = match result {
-                        Ok(v) => v,
-                        Err(err) => panic!("<Bugi-Wasm> Call-Univ-Error: emit error during running function({}:{}) \n{}", &arg.id, &arg.name, err)
-                    };
-
-                    let mem = malloc.call(&mut caller, (res.len() as u32,));
-
-                    let mem_ptr = match mem {
-                        Ok(ptr) => ptr,
-                        Err(err) => panic!("<Bugi-Wasm> Can't Alloc Memory: {}", err)
-                    };
-
-                    let result = memory.write(&mut caller, mem_ptr as usize, &res);
-                    if let Err(err) = result {
-                        panic!("<Bugi-Wasm> Can't Write Memory: {}", err)
-                    }
-
-                    (mem_ptr as u64) << 32 | res.len() as u64
-                },
-            )
-            .unwrap();
-
-        let ins = linker
-            .instantiate(&mut *store, &self.module)
-            .map_err(|err| {
-                bugi_core::BugiError::PluginCallError(format!(
-                    "Failed to wasm instantiate: {:?}",
-                    err
-                ))
-            })?;
-
-        let func = ins
-            .get_typed_func::<(u32, u32, u64), u64>(
-                &mut *store,
-                &format!("{}{}", SPEC_PLUGIN_FUNC, symbol),
-            )
-            .map_err(|err| {
-                bugi_core::BugiError::PluginCallError(format!(
-                    "Symbol get error({}): {}",
-                    symbol, err
-                ))
-            })?;
-
-        let malloc = ins
-            .get_typed_func::<(u32,), u32>(&mut *store, SPEC_LOW_MALLOC)
-            .map_err(|err| {
-                bugi_core::BugiError::PluginCallError(
-                    format!("{SPEC_LOW_MALLOC} get error: {err}",),
-                )
-            })?;
-
-        let free = ins
-            .get_typed_func::<(u32, u32), ()>(&mut *store, SPEC_LOW_FREE)
-            .map_err(|err| {
-                bugi_core::BugiError::PluginCallError(format!("{SPEC_LOW_FREE} get error: {err}"))
-            })?;
-
-        let memory = ins.get_memory(&mut *store, "memory").ok_or_else(|| {
-            bugi_core::BugiError::PluginCallError(
-                "memory get error: `memory` is not exported".to_string(),
-            )
-        })?;
-
-        let mem_ptr = malloc
-            .call(&mut *store, (param.len() as u32,))
-            .map_err(|err| {
-                bugi_core::BugiError::PluginCallError(format!(
-                    "can't alloc memory in `{SPEC_LOW_MALLOC}`: {err}"
-                ))
-            })?;
-
-        if let Err(err) = memory.write(&mut *store, mem_ptr as usize, param) {
-            return Err(bugi_core::BugiError::PluginCallError(format!(
-                "can't write memory: {err}"
-            )));
-        }
+        let budget = self.budget();
+        let (res, fuel_left) =
+            instance.invoke(&self.str_id, symbol, &func, param, abi, ploxy, &budget);
+        self.charge(&budget, fuel_left);
 
-        let res = func
-            .call(&mut *store, (mem_ptr, param.len() as u32, abi))
-            .map_err(|err| {
-                bugi_core::BugiError::PluginCallError(format!(
-                    "emit error during running `{symbol}`: {err}"
-                ))
-            })?;
-
-        let res_ptr = (res >> 32) as u32;
-        let res_len = (res & 0xFFFFFFFF) as u32;
-
-        let mut res = vec![0; res_len as usize];
-        if let Err(err) = memory.read(&mut *store, res_ptr as usize, &mut res) {
-            return Err(bugi_core::BugiError::PluginCallError(format!(
-                "can't read memory: {err}"
-            )));
+        match res {
+            Ok(res) => {
+                self.checkin(Some(instance), lease);
+                Ok(res)
+            }
+            Err(err) => {
+                self.checkin(None, lease);
+                Err(err)
+            }
         }
+    }
 
-        if let Err(err) = free.call(&mut *store, (res_ptr, res_len)) {
-            return Err(bugi_core::BugiError::PluginCallError(format!(
-                "can't dealloc memory: {err}"
-            )));
+    fn raw_call_async<'a>(
+        &'a self,
+        symbol: &'a str,
+        param: &'a [u8],
+        abi: u64,
+        ploxy: bugi_core::EnvPloxy,
+    ) -> bugi_core::BoxFuture<'a, Result<Vec<u8>, bugi_core::BugiError>> {
+        // without async support the call can't yield
+        if !self.runtime.async_support() {
+            return Box::pin(async move { self.raw_call(symbol, param, abi, ploxy) });
         }
 
-        ploxy.set_global(STORE_KEY, store);
+        Box::pin(async move {
+            let (mut instance, checkout) = self.checkout_async(symbol).await?;
+
+            let func = match instance.func(symbol) {
+                Ok(func) => func,
+                Err(err) => {
+                    checkout.checkin(Some(instance));
+                    return Err(err);
+                }
+            };
+
+            let budget = self.budget();
+            let (res, fuel_left) = instance
+                .invoke_async(&self.str_id, symbol, &func, param, abi, ploxy, &budget)
+                .await;
+            self.charge(&budget, fuel_left);
+
+            match res {
+                Ok(res) => {
+                    checkout.checkin(Some(instance));
+                    Ok(res)
+                }
+                Err(err) => {
+                    checkout.checkin(None);
+                    Err(err)
+                }
+            }
+        })
+    }
 
-        Ok(res)
+    fn reset(&self) -> Result<(), bugi_core::BugiError> {
+        WasmPlugin::reset(self);
+        Ok(())
     }
 }
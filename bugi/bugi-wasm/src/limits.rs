@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use bugi_core::ExecutionLimit;
+
+/// Length of an epoch tick
+pub(crate) const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Epoch deadline used when a call has no deadline
+pub(crate) const NO_DEADLINE: u64 = u64::MAX / 2;
+
+/// Execution limits of a wasm plugin
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ExecLimits {
+    pub fuel_per_call: Option<u64>,
+    pub fuel_total: Option<u64>,
+    pub deadline: Option<Duration>,
+}
+
+/// Limits of a single call
+pub(crate) struct CallBudget {
+    pub fuel: u64,
+    /// the limit reported when the fuel runs out
+    pub fuel_limit: Option<ExecutionLimit>,
+    pub epoch_ticks: u64,
+}
+
+impl ExecLimits {
+    /// Budget of a call, given the total fuel left
+    pub fn budget(&self, fuel_left: Option<u64>) -> CallBudget {
+        let (fuel, fuel_limit) = match (self.fuel_per_call, fuel_left) {
+            (Some(call), Some(left)) if left <= call => (left, Some(ExecutionLimit::TotalFuel)),
+            (Some(call), _) => (call, Some(ExecutionLimit::CallFuel)),
+            (None, Some(left)) => (left, Some(ExecutionLimit::TotalFuel)),
+            (None, None) => (u64::MAX, None),
+        };
+
+        let epoch_ticks = match self.deadline {
+            Some(deadline) => (deadline.as_millis().div_ceil(EPOCH_TICK.as_millis()) as u64).max(1),
+            None => NO_DEADLINE,
+        };
+
+        CallBudget {
+            fuel,
+            fuel_limit,
+            epoch_ticks,
+        }
+    }
+}
+
+impl CallBudget {
+    /// Which limit a trap came from
+    pub fn exceeded(&self, err: &anyhow::Error) -> Option<ExecutionLimit> {
+        match err.downcast_ref::<wasmtime::Trap>()? {
+            wasmtime::Trap::OutOfFuel => self.fuel_limit,
+            wasmtime::Trap::Interrupt => Some(ExecutionLimit::Deadline),
+            _ => None,
+        }
+    }
+}
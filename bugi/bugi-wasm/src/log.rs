@@ -0,0 +1,52 @@
+use bugi_core::{LogLevel, LogRecord};
+use wasmtime::Caller;
+
+use crate::instance::{outside_call, WasmState};
+
+/// Read a UTF-8 string the guest owns
+fn read_str(caller: &Caller<'_, WasmState>, ptr: u32, len: u32) -> anyhow::Result<String> {
+    let memory = caller.data().memory.ok_or_else(outside_call)?;
+    let mut bytes = vec![0; len as usize];
+    memory.read(caller, ptr as usize, &mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// `bugi@v0` `log` import.
+/// The message stays owned by the guest.
+pub(crate) fn log(
+    caller: Caller<'_, WasmState>,
+    level: u32,
+    msg_ptr: u32,
+    msg_len: u32,
+) -> anyhow::Result<()> {
+    let level =
+        LogLevel::from_u32(level).ok_or_else(|| anyhow::anyhow!("invalid log level: {level}"))?;
+
+    let message = read_str(&caller, msg_ptr, msg_len)?;
+
+    let state = caller.data();
+    let (plugin_id, symbol) = state.caller.clone();
+    let ploxy = state.ploxy.as_ref().ok_or_else(outside_call)?;
+    ploxy.log(LogRecord {
+        plugin_id,
+        symbol,
+        level,
+        message,
+    });
+    Ok(())
+}
+
+/// `bugi@v0` `panic` import.
+/// Keeps the message until the guest traps, the call then fails with `BugiError::PluginPanicked`.
+pub(crate) fn panic(
+    mut caller: Caller<'_, WasmState>,
+    msg_ptr: u32,
+    msg_len: u32,
+    loc_ptr: u32,
+    loc_len: u32,
+) -> anyhow::Result<()> {
+    let message = read_str(&caller, msg_ptr, msg_len)?;
+    let location = read_str(&caller, loc_ptr, loc_len)?;
+    caller.data_mut().panic = Some((message, location));
+    Ok(())
+}
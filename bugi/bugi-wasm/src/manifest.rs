@@ -0,0 +1,73 @@
+use bugi_core::{Capability, PluginMetadata, SymbolSignature};
+use rmpv::Value;
+
+/// Read the `bugi@v0_plugin_manifest` custom section, a messagepack map
+pub(crate) fn parse(data: &[u8]) -> Result<PluginMetadata, String> {
+    let value = rmpv::decode::read_value(&mut &data[..]).map_err(|err| err.to_string())?;
+    let map = value.as_map().ok_or("it isn't a map")?;
+
+    let mut metadata = PluginMetadata::default();
+    for (key, value) in map {
+        match key.as_str().ok_or("a key isn't a string")? {
+            "version" => metadata.version = Some(string(value, "version")?),
+            "description" => metadata.description = Some(string(value, "description")?),
+            "authors" => metadata.authors = strings(value, "authors")?,
+            "dependencies" => metadata.dependencies = strings(value, "dependencies")?,
+            "capabilities" => {
+                metadata.capabilities = array(value, "capabilities")?
+                    .iter()
+                    .map(|cap| {
+                        Ok(Capability {
+                            plugin: string(field(cap, "plugin")?, "capabilities.plugin")?,
+                            symbol: string(field(cap, "symbol")?, "capabilities.symbol")?,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?
+            }
+            "symbols" => {
+                metadata.symbols = array(value, "symbols")?
+                    .iter()
+                    .map(|sym| {
+                        Ok(SymbolSignature {
+                            name: string(field(sym, "name")?, "symbols.name")?,
+                            params: strings(field(sym, "params")?, "symbols.params")?,
+                            result: string(field(sym, "result")?, "symbols.result")?,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?
+            }
+            // unknown keys are left for later versions of the spec
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, String> {
+    value
+        .as_map()
+        .and_then(|map| map.iter().find(|(key, _)| key.as_str() == Some(name)))
+        .map(|(_, value)| value)
+        .ok_or_else(|| format!("`{name}` is not found"))
+}
+
+fn string(value: &Value, name: &str) -> Result<String, String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("`{name}` isn't a string"))
+}
+
+fn array<'a>(value: &'a Value, name: &str) -> Result<&'a [Value], String> {
+    value
+        .as_array()
+        .map(Vec::as_slice)
+        .ok_or_else(|| format!("`{name}` isn't an array"))
+}
+
+fn strings(value: &Value, name: &str) -> Result<Vec<String>, String> {
+    array(value, name)?
+        .iter()
+        .map(|value| string(value, name))
+        .collect()
+}
@@ -0,0 +1,302 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Once, Weak,
+    },
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{limits::EPOCH_TICK, parse_custom_section, WasmPlugin};
+
+/// The runtime used by `WasmPlugin::load` and `WasmPlugin::load_bin`
+pub(crate) static DEFAULT_RUNTIME: LazyLock<WasmRuntime> =
+    LazyLock::new(|| WasmRuntime::builder().build().unwrap());
+
+/// Optimization level of the Cranelift code generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    None,
+    Speed,
+    SpeedAndSize,
+}
+
+/// How instances get their memories and tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceAllocation {
+    /// allocate on each instantiation
+    OnDemand,
+    /// reuse slots of a preallocated pool
+    Pooling,
+}
+
+/// Owns a wasmtime engine. Plugins are loaded from it.
+#[derive(Clone)]
+pub struct WasmRuntime(Arc<RuntimeInner>);
+
+struct RuntimeInner {
+    engine: wasmtime::Engine,
+    ticker: Once,
+    cache_dir: Option<PathBuf>,
+    async_support: bool,
+    fuel: bool,
+    epoch_interruption: bool,
+}
+
+/// Builder of a `WasmRuntime`
+pub struct WasmRuntimeBuilder {
+    config: wasmtime::Config,
+    cache_dir: Option<PathBuf>,
+    async_support: bool,
+    fuel: bool,
+    epoch_interruption: bool,
+}
+
+impl WasmRuntime {
+    /// A builder with the default configuration (pooling allocation)
+    pub fn builder() -> WasmRuntimeBuilder {
+        let mut config = wasmtime::Config::new();
+        config.allocation_strategy(wasmtime::InstanceAllocationStrategy::pooling());
+        WasmRuntimeBuilder {
+            config,
+            cache_dir: None,
+            async_support: false,
+            fuel: false,
+            epoch_interruption: false,
+        }
+    }
+
+    pub fn load(&self, path: impl AsRef<Path>) -> anyhow::Result<WasmPlugin> {
+        self.load_bin(&std::fs::read(path)?)
+    }
+
+    pub fn load_bin(&self, bin: &[u8]) -> anyhow::Result<WasmPlugin> {
+        let section = parse_custom_section(bin)?;
+        let module = match &self.0.cache_dir {
+            Some(dir) => load_cached(&self.0.engine, bin, dir)?,
+            None => wasmtime::Module::new(&self.0.engine, bin)?,
+        };
+        WasmPlugin::new(self.clone(), module, section)
+    }
+
+    /// Compile a module into the cache directory, so that loading it skips codegen.
+    /// Returns the path of the compiled module.
+    pub fn precompile(&self, path: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
+        let dir = self
+            .0
+            .cache_dir
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("the runtime has no cache directory"))?;
+        precompile_into(&self.0.engine, &std::fs::read(path)?, dir)
+    }
+
+    pub(crate) fn engine(&self) -> &wasmtime::Engine {
+        &self.0.engine
+    }
+
+    pub(crate) fn async_support(&self) -> bool {
+        self.0.async_support
+    }
+
+    pub(crate) fn fuel(&self) -> bool {
+        self.0.fuel
+    }
+
+    pub(crate) fn epoch_interruption(&self) -> bool {
+        self.0.epoch_interruption
+    }
+
+    /// Start the thread incrementing the epoch. It stops with the runtime.
+    pub(crate) fn start_epoch_ticker(&self) {
+        self.0.ticker.call_once(|| {
+            let runtime = Arc::downgrade(&self.0);
+            std::thread::spawn(move || epoch_ticker(runtime));
+        });
+    }
+}
+
+/// Cache file of `bin` compiled by `engine`
+fn cache_path(engine: &wasmtime::Engine, bin: &[u8], dir: &Path) -> PathBuf {
+    // the engine's hash is only stable within one build of wasmtime, it is hashed in as is
+    let mut hasher = Sha256::new();
+    engine
+        .precompile_compatibility_hash()
+        .hash(&mut Sha256Hasher(&mut hasher));
+    hasher.update(bin);
+
+    let key = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+
+    dir.join(format!("{key}.cwasm"))
+}
+
+/// Feeds `Hash` implementations into a SHA-256 digest
+struct Sha256Hasher<'a>(&'a mut Sha256);
+
+impl Hasher for Sha256Hasher<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// not used, the digest is read from the `Sha256`
+    fn finish(&self) -> u64 {
+        0
+    }
+}
+
+pub(crate) fn precompile_into(
+    engine: &wasmtime::Engine,
+    bin: &[u8],
+    dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let path = cache_path(engine, bin, dir);
+    if !path.exists() {
+        let compiled = engine.precompile_module(bin)?;
+        write_cache(&path, &compiled)?;
+    }
+    Ok(path)
+}
+
+/// write through a temporary file, so that a reader never sees half of the module
+fn write_cache(path: &Path, compiled: &[u8]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    // unique per process and write, so that concurrent writers don't share a temporary file
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let tmp = path.with_extension(format!("{}-{write}.tmp", std::process::id()));
+    std::fs::write(&tmp, compiled)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn load_cached(
+    engine: &wasmtime::Engine,
+    bin: &[u8],
+    dir: &Path,
+) -> anyhow::Result<wasmtime::Module> {
+    let path = cache_path(engine, bin, dir);
+    if path.exists() {
+        // SAFETY: the cache directory only holds modules written by `precompile_into`.
+        // The key includes the engine configuration, and an incompatible file is rejected.
+        if let Ok(module) = unsafe { wasmtime::Module::deserialize_file(engine, &path) } {
+            return Ok(module);
+        }
+    }
+
+    let module = wasmtime::Module::new(engine, bin)?;
+    // the cache is best effort, a failed write only costs the next load a compile
+    if let Ok(compiled) = module.serialize() {
+        let _ = write_cache(&path, &compiled);
+    }
+    Ok(module)
+}
+
+fn epoch_ticker(runtime: Weak<RuntimeInner>) {
+    loop {
+        std::thread::sleep(EPOCH_TICK);
+        match runtime.upgrade() {
+            Some(runtime) => runtime.engine.increment_epoch(),
+            None => return,
+        }
+    }
+}
+
+impl WasmRuntimeBuilder {
+    /// SIMD (and relaxed SIMD) proposal
+    pub fn simd(mut self, enable: bool) -> Self {
+        self.config.wasm_simd(enable);
+        self.config.wasm_relaxed_simd(enable);
+        self
+    }
+
+    /// threads proposal
+    pub fn threads(mut self, enable: bool) -> Self {
+        self.config.wasm_threads(enable);
+        self
+    }
+
+    /// multi-memory proposal
+    pub fn multi_memory(mut self, enable: bool) -> Self {
+        self.config.wasm_multi_memory(enable);
+        self
+    }
+
+    /// memory64 proposal
+    pub fn memory64(mut self, enable: bool) -> Self {
+        self.config.wasm_memory64(enable);
+        self
+    }
+
+    pub fn opt_level(mut self, level: OptLevel) -> Self {
+        self.config.cranelift_opt_level(match level {
+            OptLevel::None => wasmtime::OptLevel::None,
+            OptLevel::Speed => wasmtime::OptLevel::Speed,
+            OptLevel::SpeedAndSize => wasmtime::OptLevel::SpeedAndSize,
+        });
+        self
+    }
+
+    /// DWARF debug info for native debuggers
+    pub fn debug_info(mut self, enable: bool) -> Self {
+        self.config.debug_info(enable);
+        self
+    }
+
+    pub fn allocation(mut self, allocation: InstanceAllocation) -> Self {
+        self.config.allocation_strategy(match allocation {
+            InstanceAllocation::OnDemand => wasmtime::InstanceAllocationStrategy::OnDemand,
+            InstanceAllocation::Pooling => wasmtime::InstanceAllocationStrategy::pooling(),
+        });
+        self
+    }
+
+    /// Cache compiled modules in `dir`.
+    /// Cached files are loaded as native code, so `dir` must not be writable by untrusted users.
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Run plugins on fibers, so that `raw_call_async` yields to the executor
+    /// while the plugin waits for an async call into the universe.
+    /// Synchronous calls on such a runtime block the thread on the async path with `block_on`,
+    /// so they must not be made from inside an async executor (e.g. a tokio worker),
+    /// and async host functions they reach must not need the executor's reactor or timers.
+    pub fn async_support(mut self, enable: bool) -> Self {
+        self.config.async_support(enable);
+        self.async_support = enable;
+        self
+    }
+
+    /// Meter the instructions plugins execute.
+    /// Required by `WasmPlugin::set_fuel_per_call` and `WasmPlugin::set_fuel_total`.
+    pub fn fuel(mut self, enable: bool) -> Self {
+        self.config.consume_fuel(enable);
+        self.fuel = enable;
+        self
+    }
+
+    /// Let running plugins be interrupted.
+    /// Required by `WasmPlugin::set_deadline`.
+    pub fn epoch_interruption(mut self, enable: bool) -> Self {
+        self.config.epoch_interruption(enable);
+        self.epoch_interruption = enable;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<WasmRuntime> {
+        Ok(WasmRuntime(Arc::new(RuntimeInner {
+            engine: wasmtime::Engine::new(&self.config)?,
+            ticker: Once::new(),
+            cache_dir: self.cache_dir,
+            async_support: self.async_support,
+            fuel: self.fuel,
+            epoch_interruption: self.epoch_interruption,
+        })))
+    }
+}
@@ -0,0 +1,164 @@
+use std::future::Future;
+
+use bugi_core::{BugiError, EnvPloxy, ERROR_ABI_ID};
+use rmpv::Value;
+use wasmtime::{Caller, Memory, TypedFunc};
+
+use crate::instance::{outside_call, WasmState};
+
+/// ARG Type of `call_univ`
+#[derive(Default)]
+struct CallUnivArg {
+    id: String,
+    name: String,
+    abi: u64,
+    detail: Vec<u8>,
+}
+
+fn decode_arg(bin: &[u8]) -> Result<CallUnivArg, String> {
+    let value = rmpv::decode::read_value(&mut &bin[..])
+        .map_err(|e| format!("`call_univ`'s arg is not messagepack: {e}"))?;
+
+    let Value::Map(map) = value else {
+        return Err("`call_univ`'s arg is not map".to_string());
+    };
+
+    let unsatisfied = |key: &str| format!("`call_univ`'s arg is not satisfied: `{key}`");
+
+    let mut arg = CallUnivArg::default();
+    for (name, value) in map {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        match name {
+            "id" => {
+                arg.id = value.as_str().ok_or_else(|| unsatisfied("id"))?.to_string();
+            }
+            "name" => {
+                arg.name = value
+                    .as_str()
+                    .ok_or_else(|| unsatisfied("name"))?
+                    .to_string();
+            }
+            "abi" => {
+                arg.abi = value.as_u64().ok_or_else(|| unsatisfied("abi"))?;
+            }
+            "detail" => {
+                arg.detail = value
+                    .as_slice()
+                    .ok_or_else(|| unsatisfied("detail"))?
+                    .to_vec();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(arg)
+}
+
+/// RESULT Type of `call_univ`
+fn encode_result(result: Result<(u64, Vec<u8>), String>) -> Vec<u8> {
+    let (abi, detail) = match result {
+        Ok(res) => res,
+        Err(err) => (ERROR_ABI_ID, err.into_bytes()),
+    };
+
+    let value = Value::Map(vec![
+        (Value::String("abi".into()), Value::Integer(abi.into())),
+        (Value::String("detail".into()), Value::Binary(detail)),
+    ]);
+
+    let mut res = Vec::new();
+    rmpv::encode::write_value(&mut res, &value).unwrap();
+    res
+}
+
+type Handles = (
+    TypedFunc<(u32,), u32>,
+    TypedFunc<(u32, u32), ()>,
+    Memory,
+    EnvPloxy,
+);
+
+/// What `call_univ` needs of the store. It only exists while a plugin call runs.
+fn handles(caller: &Caller<'_, WasmState>) -> anyhow::Result<Handles> {
+    let state = caller.data();
+    match (&state.malloc, &state.free, state.memory, &state.ploxy) {
+        (Some(malloc), Some(free), Some(memory), Some(ploxy)) => {
+            Ok((malloc.clone(), free.clone(), memory, ploxy.clone()))
+        }
+        _ => Err(outside_call()),
+    }
+}
+
+fn emit_error(arg: &CallUnivArg, err: BugiError) -> String {
+    format!(
+        "emit error during running function({}:{}): {}",
+        arg.id, arg.name, err
+    )
+}
+
+/// `bugi@v0` `call_univ` import.
+/// Errors of the call are sent back to the guest, only broken memory traps.
+pub(crate) fn call_univ(
+    mut caller: Caller<'_, WasmState>,
+    arg_ptr: u32,
+    arg_len: u32,
+) -> anyhow::Result<u64> {
+    let (malloc, free, memory, ploxy) = handles(&caller)?;
+
+    let mut arg = vec![0; arg_len as usize];
+    let read = memory.read(&caller, arg_ptr as usize, &mut arg);
+    free.call(&mut caller, (arg_ptr, arg_len))?;
+
+    let result = read
+        .map_err(|err| format!("can't read memory: {err}"))
+        .and_then(|_| decode_arg(&arg))
+        .and_then(|arg| {
+            ploxy
+                .call_univ_raw(&arg.id, &arg.name, &arg.detail, arg.abi)
+                .map(|res| (arg.abi, res))
+                .map_err(|err| emit_error(&arg, err))
+        });
+
+    let res = encode_result(result);
+
+    let mem_ptr = malloc.call(&mut caller, (res.len() as u32,))?;
+    memory.write(&mut caller, mem_ptr as usize, &res)?;
+
+    Ok((mem_ptr as u64) << 32 | res.len() as u64)
+}
+
+/// `call_univ` for a runtime with async support.
+/// The plugin is suspended while the universe runs the call.
+pub(crate) fn call_univ_async<'a>(
+    mut caller: Caller<'a, WasmState>,
+    (arg_ptr, arg_len): (u32, u32),
+) -> Box<dyn Future<Output = anyhow::Result<u64>> + Send + 'a> {
+    Box::new(async move {
+        let (malloc, free, memory, ploxy) = handles(&caller)?;
+
+        let mut arg = vec![0; arg_len as usize];
+        let read = memory.read(&caller, arg_ptr as usize, &mut arg);
+        free.call_async(&mut caller, (arg_ptr, arg_len)).await?;
+
+        let result = match read
+            .map_err(|err| format!("can't read memory: {err}"))
+            .and_then(|_| decode_arg(&arg))
+        {
+            Ok(arg) => ploxy
+                .call_univ_async_raw(&arg.id, &arg.name, &arg.detail, arg.abi)
+                .await
+                .map(|res| (arg.abi, res))
+                .map_err(|err| emit_error(&arg, err)),
+            Err(err) => Err(err),
+        };
+
+        let res = encode_result(result);
+
+        let mem_ptr = malloc.call_async(&mut caller, (res.len() as u32,)).await?;
+        memory.write(&mut caller, mem_ptr as usize, &res)?;
+
+        Ok((mem_ptr as u64) << 32 | res.len() as u64)
+    })
+}
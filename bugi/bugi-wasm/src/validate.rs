@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use bugi_core::PluginMetadata;
+use wasmtime::{ExternType, FuncType};
+
+use crate::{
+    manifest, SPEC_CALL_UNIV, SPEC_LOG, SPEC_LOW_FREE, SPEC_LOW_MALLOC, SPEC_PANIC,
+    SPEC_PLUGIN_FUNC, SPEC_PLUG_ID, SPEC_PLUG_MANIFEST,
+};
+
+/// Module name of the spec imports
+const SPEC_IMPORT_MODULE: &str = SPEC_CALL_UNIV.0;
+
+/// Every violation of spec-v0 found in a module
+#[derive(Debug)]
+pub struct SpecViolations(pub Vec<String>);
+
+impl std::fmt::Display for SpecViolations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the module doesn't conform to bugi spec-v0:")?;
+        for violation in &self.0 {
+            write!(f, "\n- {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SpecViolations {}
+
+fn signature(ty: &FuncType) -> String {
+    let params = ty.params().map(|p| p.to_string()).collect::<Vec<_>>();
+    let results = ty.results().map(|r| r.to_string()).collect::<Vec<_>>();
+    format!("({}) -> ({})", params.join(", "), results.join(", "))
+}
+
+/// Expected signatures of the spec functions
+const SIG_LOW_MALLOC: &str = "(i32) -> (i32)";
+const SIG_LOW_FREE: &str = "(i32, i32) -> ()";
+const SIG_PLUGIN_FUNC: &str = "(i32, i32, i64) -> (i64)";
+const SIG_CALL_UNIV: &str = "(i32, i32) -> (i64)";
+const SIG_LOG: &str = "(i32, i32, i32) -> ()";
+const SIG_PANIC: &str = "(i32, i32, i32, i32) -> ()";
+
+fn check_func(violations: &mut Vec<String>, kind: &str, name: &str, ty: ExternType, sig: &str) {
+    match ty {
+        ExternType::Func(ty) => {
+            let found = signature(&ty);
+            if found != sig {
+                violations.push(format!("{kind} `{name}` must be `{sig}`, found `{found}`"));
+            }
+        }
+        _ => violations.push(format!("{kind} `{name}` must be a function")),
+    }
+}
+
+/// Check the module against spec-v0. Returns the plugin ID and the manifest.
+pub(crate) fn validate(
+    module: &wasmtime::Module,
+    section: &HashMap<String, Vec<u8>>,
+) -> Result<(String, PluginMetadata), SpecViolations> {
+    let mut violations = Vec::new();
+
+    let str_id = match section.get(SPEC_PLUG_ID) {
+        Some(id) => match String::from_utf8(id.clone()) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                violations.push(format!("custom section `{SPEC_PLUG_ID}` is not UTF-8"));
+                None
+            }
+        },
+        None => {
+            violations.push(format!("custom section `{SPEC_PLUG_ID}` is not found"));
+            None
+        }
+    };
+
+    // the manifest is optional
+    let metadata = match section
+        .get(SPEC_PLUG_MANIFEST)
+        .map(|data| manifest::parse(data))
+    {
+        Some(Ok(metadata)) => metadata,
+        Some(Err(err)) => {
+            violations.push(format!(
+                "custom section `{SPEC_PLUG_MANIFEST}` is broken: {err}"
+            ));
+            PluginMetadata::default()
+        }
+        None => PluginMetadata::default(),
+    };
+    for symbol in &metadata.symbols {
+        let name = format!("{SPEC_PLUGIN_FUNC}{}", symbol.name);
+        if module.get_export(&name).is_none() {
+            violations.push(format!(
+                "manifest declares `{}`, but `{name}` is not exported",
+                symbol.name
+            ));
+        }
+    }
+
+    let (mut malloc, mut free, mut memory) = (false, false, false);
+    for export in module.exports() {
+        let (name, ty) = (export.name(), export.ty());
+        if name == SPEC_LOW_MALLOC {
+            malloc = true;
+            check_func(&mut violations, "export", name, ty, SIG_LOW_MALLOC);
+        } else if name == SPEC_LOW_FREE {
+            free = true;
+            check_func(&mut violations, "export", name, ty, SIG_LOW_FREE);
+        } else if name == "memory" {
+            memory = true;
+            if !matches!(ty, ExternType::Memory(_)) {
+                violations.push("export `memory` must be a memory".to_string());
+            }
+        } else if name.starts_with(SPEC_PLUGIN_FUNC) {
+            check_func(&mut violations, "export", name, ty, SIG_PLUGIN_FUNC);
+        }
+    }
+
+    for (found, name) in [
+        (malloc, SPEC_LOW_MALLOC),
+        (free, SPEC_LOW_FREE),
+        (memory, "memory"),
+    ] {
+        if !found {
+            violations.push(format!("export `{name}` is not found"));
+        }
+    }
+
+    for import in module.imports() {
+        let (module_name, name) = (import.module(), import.name());
+        #[cfg(feature = "wasi")]
+        if module_name == crate::wasi::WASI_MODULE {
+            continue;
+        }
+        if module_name != SPEC_IMPORT_MODULE {
+            violations.push(format!(
+                "import `{module_name}::{name}` is not from `{SPEC_IMPORT_MODULE}`"
+            ));
+        } else if name == SPEC_CALL_UNIV.1 {
+            check_func(&mut violations, "import", name, import.ty(), SIG_CALL_UNIV);
+        } else if name == SPEC_LOG.1 {
+            check_func(&mut violations, "import", name, import.ty(), SIG_LOG);
+        } else if name == SPEC_PANIC.1 {
+            check_func(&mut violations, "import", name, import.ty(), SIG_PANIC);
+        } else {
+            violations.push(format!(
+                "import `{module_name}::{name}` is not defined by the spec"
+            ));
+        }
+    }
+
+    match str_id {
+        Some(str_id) if violations.is_empty() => Ok((str_id, metadata)),
+        _ => Err(SpecViolations(violations)),
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    any::Any,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use bugi_core::{EnvPloxy, LogLevel, LogRecord};
+use wasi_common::{
+    dir::{OpenResult, ReaddirCursor, ReaddirEntity},
+    file::{FdFlags, Filestat, OFlags},
+    pipe::WritePipe,
+    sync::{ambient_authority, Dir, WasiCtxBuilder},
+    Error, ErrorExt, WasiCtx,
+};
+
+/// Module name of the WASI preview1 imports
+pub(crate) const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// What a plugin may touch through WASI.
+/// The default allows nothing: no args, no env, no directories and null stdio.
+#[derive(Debug, Clone, Default)]
+pub struct WasiConfig {
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub preopened_dirs: Vec<WasiDir>,
+    /// read the host's stdin; otherwise stdin is empty
+    pub inherit_stdin: bool,
+    pub stdout: WasiOutput,
+    pub stderr: WasiOutput,
+}
+
+/// A host directory opened for the plugin
+#[derive(Debug, Clone)]
+pub struct WasiDir {
+    pub host_path: PathBuf,
+    /// path the plugin sees
+    pub guest_path: String,
+    /// forbid creating, writing and removing files
+    pub read_only: bool,
+}
+
+/// Where stdout or stderr goes
+#[derive(Debug, Clone, Copy, Default)]
+pub enum WasiOutput {
+    /// discard the output
+    #[default]
+    Null,
+    /// write to the host's stream
+    Inherit,
+    /// keep up to the given number of bytes, readable through `WasmPlugin::wasi_stdout`/`wasi_stderr`
+    Capture(usize),
+    /// pass each line to the log sink of the universe at the given level
+    Log(LogLevel),
+}
+
+/// Handle of a captured output. It stays valid after the plugin is added to a `Universe`.
+#[derive(Clone)]
+pub struct WasiCapture(Arc<RwLock<CaptureBuf>>);
+
+struct CaptureBuf {
+    buf: Vec<u8>,
+    capacity: usize,
+}
+
+impl Write for CaptureBuf {
+    /// bytes beyond the capacity are dropped
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let room = self.capacity.saturating_sub(self.buf.len());
+        self.buf.extend_from_slice(&data[..data.len().min(room)]);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WasiCapture {
+    fn new(capacity: usize) -> Self {
+        Self(Arc::new(RwLock::new(CaptureBuf {
+            buf: Vec::new(),
+            capacity,
+        })))
+    }
+
+    /// Everything written so far
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.read().unwrap().buf.clone()
+    }
+
+    fn pipe(&self) -> Box<WritePipe<CaptureBuf>> {
+        Box::new(WritePipe::from_shared(self.0.clone()))
+    }
+}
+
+/// Lines of a `WasiOutput::Log` stream, logged on behalf of the running call
+struct LineLog {
+    level: LogLevel,
+    /// ploxy, plugin ID and symbol of the running call
+    call: Option<(EnvPloxy, String, String)>,
+    /// the unfinished line
+    line: Vec<u8>,
+}
+
+impl LineLog {
+    fn log_line(&self, line: &[u8]) {
+        // output written outside of a call (e.g. by a start function) is dropped
+        let Some((ploxy, plugin_id, symbol)) = &self.call else {
+            return;
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        ploxy.log(LogRecord {
+            plugin_id: plugin_id.clone(),
+            symbol: symbol.clone(),
+            level: self.level,
+            message: String::from_utf8_lossy(line).into_owned(),
+        });
+    }
+}
+
+impl Write for LineLog {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.line.extend_from_slice(data);
+        while let Some(end) = self.line.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.line.drain(..=end).collect();
+            self.log_line(&line[..end]);
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The `WasiOutput::Log` streams of an instance
+#[derive(Default)]
+pub(crate) struct WasiLogs(Vec<Arc<RwLock<LineLog>>>);
+
+impl WasiLogs {
+    fn add(&mut self, level: LogLevel) -> Box<WritePipe<LineLog>> {
+        let log = Arc::new(RwLock::new(LineLog {
+            level,
+            call: None,
+            line: Vec::new(),
+        }));
+        self.0.push(log.clone());
+        Box::new(WritePipe::from_shared(log))
+    }
+
+    /// Log on behalf of a call until `end`
+    pub fn begin(&self, ploxy: &EnvPloxy, id: &str, symbol: &str) {
+        for log in &self.0 {
+            log.write().unwrap().call = Some((ploxy.clone(), id.to_string(), symbol.to_string()));
+        }
+    }
+
+    /// Log the unfinished lines of the call
+    pub fn end(&self) {
+        for log in &self.0 {
+            let mut log = log.write().unwrap();
+            let line = std::mem::take(&mut log.line);
+            if !line.is_empty() {
+                log.log_line(&line);
+            }
+            log.call = None;
+        }
+    }
+}
+
+/// WASI configuration of a plugin with its capture buffers.
+/// The buffers are shared by every instance of the plugin.
+#[derive(Default)]
+pub(crate) struct WasiSetup {
+    config: WasiConfig,
+    pub stdout: Option<WasiCapture>,
+    pub stderr: Option<WasiCapture>,
+}
+
+impl WasiSetup {
+    /// Check the configuration by building a context once
+    pub fn new(config: WasiConfig) -> anyhow::Result<Self> {
+        let capture = |output| match output {
+            WasiOutput::Capture(capacity) => Some(WasiCapture::new(capacity)),
+            _ => None,
+        };
+        let setup = Self {
+            stdout: capture(config.stdout),
+            stderr: capture(config.stderr),
+            config,
+        };
+        setup.ctx()?;
+        Ok(setup)
+    }
+
+    /// Build the context of a new instance, with its log streams
+    pub fn ctx(&self) -> anyhow::Result<(WasiCtx, WasiLogs)> {
+        let config = &self.config;
+        let mut logs = WasiLogs::default();
+        let mut builder = WasiCtxBuilder::new();
+        builder.args(&config.args)?;
+        builder.envs(&config.env)?;
+        if config.inherit_stdin {
+            builder.inherit_stdin();
+        }
+        match (config.stdout, &self.stdout) {
+            (WasiOutput::Inherit, _) => {
+                builder.inherit_stdout();
+            }
+            (WasiOutput::Log(level), _) => {
+                builder.stdout(logs.add(level));
+            }
+            (_, Some(capture)) => {
+                builder.stdout(capture.pipe());
+            }
+            _ => {}
+        }
+        match (config.stderr, &self.stderr) {
+            (WasiOutput::Inherit, _) => {
+                builder.inherit_stderr();
+            }
+            (WasiOutput::Log(level), _) => {
+                builder.stderr(logs.add(level));
+            }
+            (_, Some(capture)) => {
+                builder.stderr(capture.pipe());
+            }
+            _ => {}
+        }
+        let ctx = builder.build();
+
+        for dir in &config.preopened_dirs {
+            let host = Dir::open_ambient_dir(&dir.host_path, ambient_authority())?;
+            let host: Box<dyn wasi_common::WasiDir> =
+                Box::new(wasi_common::sync::dir::Dir::from_cap_std(host));
+            let host = match dir.read_only {
+                true => Box::new(ReadOnlyDir(host)),
+                false => host,
+            };
+            ctx.push_preopened_dir(host, &dir.guest_path)?;
+        }
+        Ok((ctx, logs))
+    }
+}
+
+/// A directory that only passes reads through
+struct ReadOnlyDir(Box<dyn wasi_common::WasiDir>);
+
+#[async_trait::async_trait]
+impl wasi_common::WasiDir for ReadOnlyDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        if write || oflags.intersects(OFlags::CREATE | OFlags::TRUNCATE) {
+            return Err(Error::perm());
+        }
+        match self
+            .0
+            .open_file(symlink_follow, path, oflags, read, write, fdflags)
+            .await?
+        {
+            OpenResult::Dir(dir) => Ok(OpenResult::Dir(Box::new(ReadOnlyDir(dir)))),
+            file => Ok(file),
+        }
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        self.0.readdir(cursor).await
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        self.0.read_link(path).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.0.get_filestat().await
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.0.get_path_filestat(path, follow_symlinks).await
+    }
+}
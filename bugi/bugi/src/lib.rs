@@ -1,12 +1,23 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock, Weak},
+    time::Instant,
 };
 
+use metrics::Metrics;
+use middleware::Layer;
 use plugin::{Plugin, PluginRef};
+use policy::Policy;
 
+mod metrics;
+mod middleware;
 mod r#override;
 mod plugin;
+mod policy;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "plug-wasm")]
+mod watch;
 
 // --- Re-exports ---
 
@@ -15,9 +26,18 @@ pub use bugi_core::*;
 #[allow(unused_imports)]
 pub use bugi_share::*;
 
+#[allow(unused_imports)]
+pub use metrics::*;
+
+#[allow(unused_imports)]
+pub use middleware::*;
+
 #[allow(unused_imports)]
 pub use plugin::*;
 
+#[allow(unused_imports)]
+pub use policy::*;
+
 #[allow(unused_imports)]
 pub use r#override::*;
 
@@ -27,8 +47,26 @@ pub use bugi_host::*;
 #[cfg(feature = "plug-wasm")]
 pub use bugi_wasm::*;
 
+#[cfg(feature = "plug-dylib")]
+pub use bugi_dylib::*;
+
+#[cfg(feature = "plug-process")]
+pub use bugi_process::*;
+
+#[cfg(feature = "plug-remote")]
+pub use bugi_remote::*;
+
+#[cfg(feature = "plug-rhai")]
+pub use bugi_rhai::*;
+
+#[cfg(feature = "plug-wasm")]
+pub use watch::*;
+
 // --- Universe ---
 
+/// How deep plugins may call each other unless `Universe::set_max_call_depth` is used
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
 /// Stores plugins
 #[derive(Clone)]
 pub struct Universe(Arc<RwLock<UniverseInner>>);
@@ -42,6 +80,12 @@ struct UniverseInner {
     plugins: HashMap<PluginId, Arc<Plugin>>,
     str_ids: HashMap<String, PluginId>,
     next_id: PluginId,
+    log_sink: Option<Arc<dyn LogSink>>,
+    max_call_depth: usize,
+    /// cloned out by calls, so adding a layer never waits for them
+    middleware: Arc<Vec<Layer>>,
+    metrics: Arc<Metrics>,
+    policy: Policy,
 }
 
 impl Universe {
@@ -51,19 +95,139 @@ impl Universe {
             plugins: HashMap::new(),
             str_ids: HashMap::new(),
             next_id: 0,
+            log_sink: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            middleware: Arc::new(Vec::new()),
+            metrics: Arc::default(),
+            policy: Policy::default(),
         })))
     }
 
+    /// Set where the log records of plugins go. Records are dropped until this is set.
+    pub fn set_log_sink(&self, sink: impl LogSink + 'static) {
+        self.0.write().unwrap().log_sink = Some(Arc::new(sink));
+    }
+
+    pub(crate) fn log_sink(&self) -> Option<Arc<dyn LogSink>> {
+        self.0.read().unwrap().log_sink.clone()
+    }
+
+    /// Set how many plugin functions a call chain may go through,
+    /// counting the one called from the host.
+    /// Deeper calls fail with `BugiError::CallDepthExceeded`.
+    pub fn set_max_call_depth(&self, depth: usize) {
+        self.0.write().unwrap().max_call_depth = depth;
+    }
+
+    pub(crate) fn max_call_depth(&self) -> usize {
+        self.0.read().unwrap().max_call_depth
+    }
+
+    /// Run `f` before every call of a plugin, including the calls plugins make to each other.
+    /// Returning an error stops the call with it.
+    pub fn before_call(
+        &self,
+        f: impl Fn(&CallInfo) -> Result<(), BugiError> + Send + Sync + 'static,
+    ) {
+        self.add_layer(Layer::Before(Arc::new(f)))
+    }
+
+    /// Run `f` with the result of every call of a plugin, including the calls plugins make to each other.
+    pub fn after_call(
+        &self,
+        f: impl Fn(&CallInfo, &Result<Vec<u8>, BugiError>) + Send + Sync + 'static,
+    ) {
+        self.add_layer(Layer::After(Arc::new(f)))
+    }
+
+    /// Wrap every call of a plugin, including the calls plugins make to each other.
+    /// `f` runs the call with `Next::run`, or returns its own result instead.
+    /// Async calls can't be wrapped, so they skip these layers.
+    pub fn around_call(
+        &self,
+        f: impl Fn(&CallInfo, Next) -> Result<Vec<u8>, BugiError> + Send + Sync + 'static,
+    ) {
+        self.add_layer(Layer::Around(Arc::new(f)))
+    }
+
+    fn add_layer(&self, layer: Layer) {
+        Arc::make_mut(&mut self.0.write().unwrap().middleware).push(layer);
+    }
+
+    /// Middleware and metrics of a call, cloned out so the call doesn't hold the lock
+    fn layers(&self) -> (Arc<Vec<Layer>>, Arc<Metrics>) {
+        let inner = self.0.read().unwrap();
+        (inner.middleware.clone(), inner.metrics.clone())
+    }
+
+    /// Allow the plugin `caller` to call what `capability` covers
+    pub fn grant(&self, caller: &str, capability: Capability) {
+        let mut inner = self.0.write().unwrap();
+        let caps = inner.policy.grants.entry(caller.to_string()).or_default();
+        caps.push(capability);
+    }
+
+    /// Whether plugins may only call themselves and what they were granted.
+    /// Off by default, so plugins may call any plugin.
+    /// Denied calls fail with `BugiError::PermissionDenied`.
+    pub fn set_capabilities_enforced(&self, enforced: bool) {
+        self.0.write().unwrap().policy.enforced = enforced;
+    }
+
+    /// Check a call of `str_id:symbol` a plugin makes through its ploxy
+    /// against the granted capabilities.
+    /// Ploxies that build their own routing must call this before dispatching.
+    pub fn check_call(
+        &self,
+        ploxy: &EnvPloxy,
+        str_id: &str,
+        symbol: &str,
+    ) -> Result<(), BugiError> {
+        let Some(caller) = ploxy.caller() else {
+            return Ok(());
+        };
+        let inner = self.0.read().unwrap();
+        inner.policy.check(&caller.plugin_id, str_id, symbol)
+    }
+
+    /// `check_call` where "self" means the plugin `self_id`
+    pub(crate) fn check_call_id(
+        &self,
+        ploxy: &EnvPloxy,
+        str_id: &str,
+        self_id: PluginId,
+        symbol: &str,
+    ) -> Result<(), BugiError> {
+        if ploxy.caller().is_none() || !self.0.read().unwrap().policy.enforced {
+            return Ok(());
+        }
+        match str_id {
+            "self" => {
+                let target = self.plugin_by_id(self_id)?.get_str_id();
+                self.check_call(ploxy, &target, symbol)
+            }
+            _ => self.check_call(ploxy, str_id, symbol),
+        }
+    }
+
+    /// Counters of the calls of every plugin function so far,
+    /// including the calls plugins make to each other
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.0.read().unwrap().metrics.snapshot()
+    }
+
     /// Add a plugin to the Universe
     pub fn add_plugin_raw(&self, plugin: Plugin) -> Result<PluginRef, BugiError> {
+        // asking the plugin for its ID runs plugin code, so do it before locking
+        let str_id = plugin.get_str_id();
         let mut inner = self.0.write().unwrap();
 
         // Check ID
         // TODO: This is O(n) and can be optimized to use a HashSet
         // Modify this if performance becomes an issue
         for (s, _) in inner.str_ids.iter() {
-            if *s == plugin.get_str_id() {
-                return Err(BugiError::PluginIdExists(plugin.get_str_id().to_string()));
+            if *s == str_id {
+                return Err(BugiError::PluginIdExists(str_id));
             }
         }
 
@@ -73,7 +237,7 @@ impl Universe {
         let plugin = Arc::new(plugin);
 
         inner.plugins.insert(id, Arc::clone(&plugin));
-        inner.str_ids.insert(plugin.get_str_id(), id);
+        inner.str_ids.insert(str_id, id);
         Ok(PluginRef::new(
             Arc::downgrade(&plugin),
             id,
@@ -89,34 +253,240 @@ impl Universe {
         self.add_plugin_raw(Plugin::new(detail))
     }
 
-    pub(crate) fn call_raw(
+    /// Remove a plugin from the Universe.
+    /// Its `PluginRef`s return `BugiError::PluginDropped` afterwards.
+    pub fn remove_plugin(&self, str_id: &str) -> Result<(), BugiError> {
+        let mut inner = self.0.write().unwrap();
+        let id = inner
+            .str_ids
+            .remove(str_id)
+            .ok_or(BugiError::PluginNotFound(str_id.to_string()))?;
+        inner.plugins.remove(&id);
+        Ok(())
+    }
+
+    /// Replace the implementation of the plugin with the same string ID.
+    /// The `PluginId` is kept, so existing `PluginRef`s call the new implementation.
+    pub fn replace_plugin_raw(&self, plugin: Plugin) -> Result<PluginRef, BugiError> {
+        let str_id = plugin.get_str_id();
+        let inner = self.0.read().unwrap();
+        let id = *inner
+            .str_ids
+            .get(&str_id)
+            .ok_or(BugiError::PluginNotFound(str_id))?;
+        let current = inner.plugins.get(&id).unwrap();
+        current.replace(plugin);
+        Ok(PluginRef::new(
+            Arc::downgrade(current),
+            id,
+            UniverseWeak(Arc::downgrade(&self.0)),
+        ))
+    }
+
+    /// replace plugin with PluginSystem
+    pub fn replace_plugin(
+        &self,
+        detail: impl bugi_core::PluginSystem + 'static,
+    ) -> Result<PluginRef, BugiError> {
+        self.replace_plugin_raw(Plugin::new(detail))
+    }
+
+    /// List the string ID and the functions of every plugin, ordered by ID
+    pub fn list_plugins(&self) -> Vec<(String, Vec<PluginSymbol>)> {
+        let plugins = {
+            let inner = self.0.read().unwrap();
+            inner
+                .str_ids
+                .iter()
+                .map(|(str_id, id)| (str_id.clone(), inner.plugins[id].clone()))
+                .collect::<Vec<_>>()
+        };
+        // plugins answer without the lock, like calls
+        let mut plugins = plugins
+            .into_iter()
+            .map(|(str_id, plugin)| (str_id, plugin.symbols()))
+            .collect::<Vec<_>>();
+        plugins.sort_by(|a, b| a.0.cmp(&b.0));
+        plugins
+    }
+
+    /// Call a plugin with serialized arguments.
+    /// `ploxy` decides where the plugin's own calls into the universe go.
+    /// No lock of the universe is held while the plugin runs,
+    /// so plugins may add or remove plugins during a call.
+    /// The call is added to the call chain of `ploxy`.
+    pub fn call_raw(
+        &self,
+        str_id: &str,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        #[cfg(feature = "tracing")]
+        let _span = trace::call_raw(str_id, symbol, abi, arg);
+        let plugin = self.plugin(str_id)?;
+        self.dispatch(&plugin, str_id, symbol, arg, abi, ploxy)
+    }
+
+    pub(crate) fn call_raw_id(
         &self,
+        id: PluginId,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> Result<Vec<u8>, BugiError> {
+        let plugin = self.plugin_by_id(id)?;
+        let str_id = plugin.get_str_id();
+        #[cfg(feature = "tracing")]
+        let _span = trace::call_raw(&str_id, symbol, abi, arg);
+        self.dispatch(&plugin, &str_id, symbol, arg, abi, ploxy)
+    }
+
+    /// Run a call through the call chain and the middleware
+    pub(crate) fn dispatch(
+        &self,
+        plugin: &Plugin,
         str_id: &str,
         symbol: &str,
         arg: &[u8],
         abi: u64,
         ploxy: EnvPloxy,
     ) -> Result<Vec<u8>, BugiError> {
+        let (middleware, metrics) = self.layers();
+        let start = Instant::now();
+        let res = ploxy
+            .enter(str_id, symbol, arg, abi, self.max_call_depth())
+            .and_then(|ploxy| {
+                let call = CallInfo {
+                    plugin_id: str_id,
+                    symbol,
+                    abi,
+                    arg,
+                };
+                let dispatch = || plugin.call_raw(symbol, arg, abi, ploxy.clone());
+                Next::new(&middleware, &call, &dispatch).run()
+            });
+        metrics.record(str_id, symbol, arg, &res, start.elapsed());
+        res
+    }
+
+    /// Async version of `dispatch`. Only the `before_call` and `after_call` layers apply.
+    pub(crate) fn dispatch_async(
+        &self,
+        plugin: &Plugin,
+        str_id: &str,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> BoxFuture<'static, Result<Vec<u8>, BugiError>> {
+        let (middleware, metrics) = self.layers();
+        let start = Instant::now();
+        let future = ploxy
+            .enter(str_id, symbol, arg, abi, self.max_call_depth())
+            .map(|ploxy| plugin.call_raw_async(symbol, arg, abi, ploxy));
+        let (str_id, symbol, arg) = (str_id.to_string(), symbol.to_string(), arg.to_vec());
+        Box::pin(async move {
+            let res = match future {
+                Ok(future) => {
+                    Self::run_async(&middleware, &str_id, &symbol, abi, &arg, future).await
+                }
+                Err(err) => Err(err),
+            };
+            metrics.record(&str_id, &symbol, &arg, &res, start.elapsed());
+            res
+        })
+    }
+
+    /// Run the `before_call` and `after_call` layers around an async call
+    async fn run_async(
+        middleware: &[Layer],
+        str_id: &str,
+        symbol: &str,
+        abi: u64,
+        arg: &[u8],
+        future: BoxFuture<'static, Result<Vec<u8>, BugiError>>,
+    ) -> Result<Vec<u8>, BugiError> {
+        let call = CallInfo {
+            plugin_id: str_id,
+            symbol,
+            abi,
+            arg,
+        };
+        // layers run in the order they wrap each other in `dispatch`
+        let mut entered = 0;
+        let mut denied = None;
+        for layer in middleware.iter() {
+            if let Layer::Before(before) = layer {
+                if let Err(err) = before(&call) {
+                    denied = Some(err);
+                    break;
+                }
+            }
+            entered += 1;
+        }
+        let res = match denied {
+            Some(err) => Err(err),
+            None => future.await,
+        };
+        for layer in middleware[..entered].iter().rev() {
+            if let Layer::After(after) = layer {
+                after(&call, &res);
+            }
+        }
+        res
+    }
+
+    /// Find a plugin. The lock is released before the plugin runs.
+    fn plugin(&self, str_id: &str) -> Result<Arc<Plugin>, BugiError> {
         let inner = self.0.read().unwrap();
         let id = inner
             .str_ids
             .get(str_id)
             .ok_or(BugiError::PluginNotFound(str_id.to_string()))?;
-        let plugin = inner.plugins.get(id).unwrap();
-        plugin.call_raw(symbol, arg, abi, ploxy)
+        Ok(inner.plugins[id].clone())
     }
 
-    pub(crate) fn call_raw_id(
+    fn plugin_by_id(&self, id: PluginId) -> Result<Arc<Plugin>, BugiError> {
+        let inner = self.0.read().unwrap();
+        inner
+            .plugins
+            .get(&id)
+            .cloned()
+            .ok_or(BugiError::PluginDropped)
+    }
+
+    /// Async version of `call_raw`
+    pub fn call_raw_async(
+        &self,
+        str_id: &str,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> BoxFuture<'static, Result<Vec<u8>, BugiError>> {
+        match self.plugin(str_id) {
+            Ok(plugin) => self.dispatch_async(&plugin, str_id, symbol, arg, abi, ploxy),
+            Err(err) => Box::pin(async move { Err(err) }),
+        }
+    }
+
+    pub(crate) fn call_raw_id_async(
         &self,
         id: PluginId,
         symbol: &str,
         arg: &[u8],
         abi: u64,
         ploxy: EnvPloxy,
-    ) -> Result<Vec<u8>, BugiError> {
-        let inner = self.0.read().unwrap();
-        let plugin = inner.plugins.get(&id).unwrap();
-        plugin.call_raw(symbol, arg, abi, ploxy)
+    ) -> BoxFuture<'static, Result<Vec<u8>, BugiError>> {
+        match self.plugin_by_id(id) {
+            Ok(plugin) => {
+                self.dispatch_async(&plugin, &plugin.get_str_id(), symbol, arg, abi, ploxy)
+            }
+            Err(err) => Box::pin(async move { Err(err) }),
+        }
     }
 }
 
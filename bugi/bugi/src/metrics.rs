@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+
+use bugi_core::BugiError;
+
+/// Upper bounds of the latency buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 10] =
+    [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// How long calls took
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    /// calls per bucket of `LATENCY_BUCKETS`, then the calls slower than all of them
+    pub counts: [u64; LATENCY_BUCKETS.len() + 1],
+    /// time all calls took together
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += elapsed;
+    }
+
+    /// Number of calls observed
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Counters of one plugin function
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallMetrics {
+    pub calls: u64,
+    pub errors: u64,
+    /// serialized arguments received
+    pub bytes_in: u64,
+    /// serialized results returned
+    pub bytes_out: u64,
+    pub latency: LatencyHistogram,
+}
+
+/// Counters of every plugin function called so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// keyed by plugin string ID and symbol
+    pub calls: BTreeMap<(String, String), CallMetrics>,
+}
+
+impl MetricsSnapshot {
+    /// Counters of `plugin:symbol`, if it was called
+    pub fn get(&self, plugin: &str, symbol: &str) -> Option<&CallMetrics> {
+        self.calls.get(&(plugin.to_string(), symbol.to_string()))
+    }
+
+    /// Write the counters in the Prometheus text format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.counter(
+            &mut out,
+            "bugi_calls_total",
+            "Calls of plugin functions",
+            |m| m.calls,
+        );
+        self.counter(
+            &mut out,
+            "bugi_call_errors_total",
+            "Calls of plugin functions that failed",
+            |m| m.errors,
+        );
+        self.counter(
+            &mut out,
+            "bugi_call_bytes_in_total",
+            "Bytes of arguments passed to plugin functions",
+            |m| m.bytes_in,
+        );
+        self.counter(
+            &mut out,
+            "bugi_call_bytes_out_total",
+            "Bytes of results returned by plugin functions",
+            |m| m.bytes_out,
+        );
+
+        let name = "bugi_call_duration_seconds";
+        let _ = writeln!(out, "# HELP {name} Time plugin functions took");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for ((plugin, symbol), metrics) in &self.calls {
+            let labels = labels(plugin, symbol);
+            let latency = &metrics.latency;
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&latency.counts) {
+                cumulative += count;
+                let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}");
+            }
+            let count = latency.count();
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {count}");
+            let sum = latency.sum.as_secs_f64();
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {sum}");
+            let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+        }
+        out
+    }
+
+    fn counter(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        value: impl Fn(&CallMetrics) -> u64,
+    ) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for ((plugin, symbol), metrics) in &self.calls {
+            let labels = labels(plugin, symbol);
+            let _ = writeln!(out, "{name}{{{labels}}} {}", value(metrics));
+        }
+    }
+}
+
+fn labels(plugin: &str, symbol: &str) -> String {
+    format!(
+        "plugin=\"{}\",symbol=\"{}\"",
+        escape(plugin),
+        escape(symbol)
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counters of a universe, by plugin string ID and then symbol
+#[derive(Default)]
+pub(crate) struct Metrics(Mutex<HashMap<String, HashMap<String, CallMetrics>>>);
+
+impl Metrics {
+    pub(crate) fn record(
+        &self,
+        plugin: &str,
+        symbol: &str,
+        arg: &[u8],
+        res: &Result<Vec<u8>, BugiError>,
+        elapsed: Duration,
+    ) {
+        let mut plugins = self.0.lock().unwrap();
+        // allocate the keys only the first time a function is seen
+        if !plugins.contains_key(plugin) {
+            plugins.insert(plugin.to_string(), HashMap::new());
+        }
+        let symbols = plugins.get_mut(plugin).unwrap();
+        if !symbols.contains_key(symbol) {
+            symbols.insert(symbol.to_string(), CallMetrics::default());
+        }
+        let metrics = symbols.get_mut(symbol).unwrap();
+
+        metrics.calls += 1;
+        metrics.bytes_in += arg.len() as u64;
+        match res {
+            Ok(res) => metrics.bytes_out += res.len() as u64,
+            Err(_) => metrics.errors += 1,
+        }
+        metrics.latency.observe(elapsed);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let plugins = self.0.lock().unwrap();
+        MetricsSnapshot {
+            calls: plugins
+                .iter()
+                .flat_map(|(plugin, symbols)| {
+                    symbols.iter().map(|(symbol, metrics)| {
+                        ((plugin.clone(), symbol.clone()), metrics.clone())
+                    })
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use bugi_core::BugiError;
+
+/// A call going through the universe, as the middleware sees it
+#[derive(Debug, Clone, Copy)]
+pub struct CallInfo<'a> {
+    pub plugin_id: &'a str,
+    pub symbol: &'a str,
+    pub abi: u64,
+    /// serialized arguments
+    pub arg: &'a [u8],
+}
+
+pub(crate) type BeforeFn = dyn Fn(&CallInfo) -> Result<(), BugiError> + Send + Sync;
+pub(crate) type AfterFn = dyn Fn(&CallInfo, &Result<Vec<u8>, BugiError>) + Send + Sync;
+pub(crate) type AroundFn = dyn Fn(&CallInfo, Next) -> Result<Vec<u8>, BugiError> + Send + Sync;
+
+/// A middleware layer. Layers added first wrap the ones added later.
+#[derive(Clone)]
+pub(crate) enum Layer {
+    Before(Arc<BeforeFn>),
+    After(Arc<AfterFn>),
+    Around(Arc<AroundFn>),
+}
+
+/// The rest of the middleware and the plugin itself
+pub struct Next<'a> {
+    layers: &'a [Layer],
+    call: &'a CallInfo<'a>,
+    dispatch: &'a dyn Fn() -> Result<Vec<u8>, BugiError>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        layers: &'a [Layer],
+        call: &'a CallInfo<'a>,
+        dispatch: &'a dyn Fn() -> Result<Vec<u8>, BugiError>,
+    ) -> Self {
+        Self {
+            layers,
+            call,
+            dispatch,
+        }
+    }
+
+    /// Run the inner layers and the plugin
+    pub fn run(self) -> Result<Vec<u8>, BugiError> {
+        let Some((layer, layers)) = self.layers.split_first() else {
+            return (self.dispatch)();
+        };
+        let next = Next { layers, ..self };
+        match layer {
+            Layer::Before(before) => {
+                before(self.call)?;
+                next.run()
+            }
+            Layer::After(after) => {
+                let res = next.run();
+                after(self.call, &res);
+                res
+            }
+            Layer::Around(around) => around(self.call, next),
+        }
+    }
+}
@@ -56,6 +56,12 @@ impl Overrider {
         let ploxy = EnvPloxy::new(
             cacher,
             Box::new(move |str, symbol, arg, abi, ploxy| {
+                let univ = univw
+                    .upgrade()
+                    .ok_or_else(|| BugiError::PluginUniverseDropped)?;
+                // overridden functions stand in for the plugins, so they're checked the same
+                univ.check_call_id(&ploxy, str, id, symbol)?;
+
                 if let Some(data) =
                     s.0.read()
                         .unwrap()
@@ -69,10 +75,6 @@ impl Overrider {
                     return (data.1)(arg);
                 }
 
-                let univ = univw
-                    .upgrade()
-                    .ok_or_else(|| BugiError::PluginUniverseDropped)?;
-
                 if str == "self" {
                     univ.call_raw_id(id, symbol, arg, abi, ploxy)
                 } else {
@@ -80,6 +82,7 @@ impl Overrider {
                 }
             }),
             pref.id,
+            pref.univ_ref.upgrade().and_then(|univ| univ.log_sink()),
         );
 
         pref.call_with_ploxy(sym, param, ploxy)
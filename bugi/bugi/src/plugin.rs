@@ -1,26 +1,52 @@
-use std::sync::Weak;
-
-use bugi_core::{BugiError, EnvPloxy, PluginId, PluginSystem};
+use std::{
+    future::Future,
+    sync::{Arc, RwLock, Weak},
+};
+
+use bugi_core::{
+    BoxFuture, BugiError, EnvPloxy, PluginId, PluginMetadata, PluginSymbol, PluginSystem,
+};
 use bugi_share::{FromByte, ParamListTo, SerializeTag};
 
 use crate::UniverseWeak;
 
 /// plugin (original)
 pub struct Plugin {
-    detail: Box<dyn PluginSystem>,
+    /// swappable implementation; calls clone it out so a swap never waits for them
+    detail: RwLock<Arc<dyn PluginSystem>>,
 }
 
 impl Plugin {
     /// Create a new Host Plugin
     pub fn new(detail: impl PluginSystem + 'static) -> Self {
         Self {
-            detail: Box::new(detail),
+            detail: RwLock::new(Arc::new(detail)),
         }
     }
 
+    fn detail(&self) -> Arc<dyn PluginSystem> {
+        Arc::clone(&self.detail.read().unwrap())
+    }
+
     /// Get the string ID of the plugin
     pub fn get_str_id(&self) -> String {
-        self.detail.str_id()
+        self.detail().str_id()
+    }
+
+    /// Get the functions the plugin exposes
+    pub fn symbols(&self) -> Vec<PluginSymbol> {
+        self.detail().symbols()
+    }
+
+    /// Get what the plugin declares about itself
+    pub fn metadata(&self) -> PluginMetadata {
+        self.detail().metadata()
+    }
+
+    /// Swap the implementation with the one of `other`.
+    /// Calls already running keep using the old implementation.
+    pub(crate) fn replace(&self, other: Plugin) {
+        *self.detail.write().unwrap() = other.detail.into_inner().unwrap();
     }
 
     pub(crate) fn call_raw(
@@ -30,7 +56,24 @@ impl Plugin {
         abi: u64,
         ploxy: EnvPloxy,
     ) -> Result<Vec<u8>, BugiError> {
-        self.detail.raw_call(symbol, arg, abi, ploxy)
+        self.detail().raw_call(symbol, arg, abi, ploxy)
+    }
+
+    pub(crate) fn call_raw_async(
+        &self,
+        symbol: &str,
+        arg: &[u8],
+        abi: u64,
+        ploxy: EnvPloxy,
+    ) -> BoxFuture<'static, Result<Vec<u8>, BugiError>> {
+        let detail = self.detail();
+        let (symbol, arg) = (symbol.to_string(), arg.to_vec());
+        Box::pin(async move { detail.raw_call_async(&symbol, &arg, abi, ploxy).await })
+    }
+
+    /// Drop the state kept between calls
+    pub fn reset(&self) -> Result<(), BugiError> {
+        self.detail().reset()
     }
 }
 
@@ -51,20 +94,17 @@ impl PluginRef {
         Self { pref, id, univ_ref }
     }
 
-    /// Call the plugin
-    pub fn call<SType: SerializeTag, Output: FromByte<SType>>(
-        &self,
-        symbol: &str,
-        param: impl ParamListTo<SType>,
-    ) -> Result<Output, BugiError> {
-        let univw = self.univ_ref.clone();
+    /// Ploxy of a call, routing the plugin's calls into the universe
+    fn ploxy(&self, cacher: Option<&bugi_core::Cacher>) -> EnvPloxy {
+        let (univw, univw_async) = (self.univ_ref.clone(), self.univ_ref.clone());
         let id = self.id;
-        let env_plox = EnvPloxy::new(
-            None,
+        EnvPloxy::new(
+            cacher,
             Box::new(move |str, symbol, arg, abi, ploxy| {
                 let univ = univw
                     .upgrade()
                     .ok_or_else(|| BugiError::PluginUniverseDropped)?;
+                univ.check_call_id(&ploxy, str, id, symbol)?;
                 if str == "self" {
                     univ.call_raw_id(id, symbol, arg, abi, ploxy)
                 } else {
@@ -72,8 +112,30 @@ impl PluginRef {
                 }
             }),
             self.id,
-        );
-        self.call_with_ploxy(symbol, param, env_plox)
+            self.univ_ref.upgrade().and_then(|univ| univ.log_sink()),
+        )
+        .with_call_univ_async(Box::new(move |str, symbol, arg, abi, ploxy| {
+            let Some(univ) = univw_async.upgrade() else {
+                return Box::pin(async { Err(BugiError::PluginUniverseDropped) });
+            };
+            if let Err(err) = univ.check_call_id(&ploxy, &str, id, &symbol) {
+                return Box::pin(async { Err(err) });
+            }
+            if str == "self" {
+                univ.call_raw_id_async(id, &symbol, &arg, abi, ploxy)
+            } else {
+                univ.call_raw_async(&str, &symbol, &arg, abi, ploxy)
+            }
+        }))
+    }
+
+    /// Call the plugin
+    pub fn call<SType: SerializeTag, Output: FromByte<SType>>(
+        &self,
+        symbol: &str,
+        param: impl ParamListTo<SType>,
+    ) -> Result<Output, BugiError> {
+        self.call_with_ploxy(symbol, param, self.ploxy(None))
     }
 
     /// Call with Cacher
@@ -83,23 +145,54 @@ impl PluginRef {
         param: impl ParamListTo<SType>,
         cacher: &bugi_core::Cacher,
     ) -> Result<Output, BugiError> {
-        let univw = self.univ_ref.clone();
-        let id = self.id;
-        let env_plox = EnvPloxy::new(
-            Some(cacher),
-            Box::new(move |str, symbol, arg, abi, ploxy| {
-                let univ = univw
+        self.call_with_ploxy(symbol, param, self.ploxy(Some(cacher)))
+    }
+
+    /// Call the plugin without blocking an async executor.
+    /// No lock of the universe is held while the call runs.
+    pub fn call_async<SType: SerializeTag, Output: FromByte<SType>>(
+        &self,
+        symbol: &str,
+        param: impl ParamListTo<SType>,
+    ) -> impl Future<Output = Result<Output, BugiError>> + Send + 'static {
+        let call = self
+            .pref
+            .upgrade()
+            .ok_or(BugiError::PluginDropped)
+            .and_then(|plug| {
+                let param = param.to_byte().map_err(BugiError::CannotSerialize)?;
+                let univ = self
+                    .univ_ref
                     .upgrade()
-                    .ok_or_else(|| BugiError::PluginUniverseDropped)?;
-                if str == "self" {
-                    univ.call_raw_id(id, symbol, arg, abi, ploxy)
-                } else {
-                    univ.call_raw(str, symbol, arg, abi, ploxy)
-                }
-            }),
-            self.id,
-        );
-        self.call_with_ploxy(symbol, param, env_plox)
+                    .ok_or(BugiError::PluginUniverseDropped)?;
+                Ok(univ.dispatch_async(
+                    &plug,
+                    &plug.get_str_id(),
+                    symbol,
+                    &param,
+                    SType::get_abi_id(),
+                    self.ploxy(None),
+                ))
+            });
+        async move { Ok(Output::from_byte(&call?.await?)?) }
+    }
+
+    /// Get the functions the plugin exposes
+    pub fn symbols(&self) -> Result<Vec<PluginSymbol>, BugiError> {
+        let plug = self.pref.upgrade().ok_or(BugiError::PluginDropped)?;
+        Ok(plug.symbols())
+    }
+
+    /// Get what the plugin declares about itself, without calling it
+    pub fn metadata(&self) -> Result<PluginMetadata, BugiError> {
+        let plug = self.pref.upgrade().ok_or(BugiError::PluginDropped)?;
+        Ok(plug.metadata())
+    }
+
+    /// Drop the state the plugin keeps between calls
+    pub fn reset(&self) -> Result<(), BugiError> {
+        let plug = self.pref.upgrade().ok_or(BugiError::PluginDropped)?;
+        plug.reset()
     }
 
     pub(crate) fn call_with_ploxy<SType: SerializeTag, Output: FromByte<SType>>(
@@ -111,8 +204,16 @@ impl PluginRef {
         let plug = self.pref.upgrade().ok_or(BugiError::PluginDropped)?;
 
         let param = param.to_byte().map_err(BugiError::CannotSerialize)?;
+        let univ = self
+            .univ_ref
+            .upgrade()
+            .ok_or(BugiError::PluginUniverseDropped)?;
+
+        let (str_id, abi) = (plug.get_str_id(), SType::get_abi_id());
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::call(&str_id, symbol, abi, &param);
 
-        let result = plug.call_raw(symbol, &param, SType::get_abi_id(), ploxy)?;
+        let result = univ.dispatch(&plug, &str_id, symbol, &param, abi, ploxy)?;
 
         Ok(Output::from_byte(&result)?)
     }
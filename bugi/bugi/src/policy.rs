@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use bugi_core::{BugiError, Capability};
+
+/// Which plugins may call which
+#[derive(Default)]
+pub(crate) struct Policy {
+    pub(crate) enforced: bool,
+    pub(crate) grants: HashMap<String, Vec<Capability>>,
+}
+
+impl Policy {
+    /// Check a call of `plugin:symbol` made by the plugin `caller`.
+    /// Plugins may always call themselves.
+    pub(crate) fn check(&self, caller: &str, plugin: &str, symbol: &str) -> Result<(), BugiError> {
+        let granted = self
+            .grants
+            .get(caller)
+            .is_some_and(|caps| caps.iter().any(|cap| cap.allows(plugin, symbol)));
+        if !self.enforced || caller == plugin || granted {
+            Ok(())
+        } else {
+            Err(BugiError::PermissionDenied {
+                caller: caller.to_string(),
+                plugin: plugin.to_string(),
+                symbol: symbol.to_string(),
+            })
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use bugi_core::CallSpan;
+
+/// Span of a call from the host through `PluginRef`
+pub(crate) fn call(plugin: &str, symbol: &str, abi: u64, arg: &[u8]) -> CallSpan {
+    CallSpan::enter(tracing::debug_span!(
+        "plugin_call",
+        plugin,
+        symbol,
+        abi,
+        payload = arg.len(),
+        duration_us = tracing::field::Empty,
+    ))
+}
+
+/// Span of a call dispatched by the universe
+pub(crate) fn call_raw(plugin: &str, symbol: &str, abi: u64, arg: &[u8]) -> CallSpan {
+    CallSpan::enter(tracing::debug_span!(
+        "universe_call",
+        plugin,
+        symbol,
+        abi,
+        payload = arg.len(),
+        duration_us = tracing::field::Empty,
+    ))
+}
@@ -0,0 +1,168 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bugi_core::BugiError;
+use bugi_wasm::WasmPlugin;
+
+use crate::{plugin::PluginRef, Universe, UniverseWeak};
+
+/// Reloads a wasm plugin into its Universe when the `.wasm` file changes
+pub struct WasmWatcher {
+    inner: Arc<WatcherInner>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Loads and configures the plugin from the watched file
+type WasmLoader = dyn Fn(&Path) -> anyhow::Result<WasmPlugin> + Send + Sync;
+
+struct WatcherInner {
+    univ: UniverseWeak,
+    path: PathBuf,
+    loader: Box<WasmLoader>,
+    str_id: String,
+    /// (modified time, length) of the file at the last check
+    stamp: Mutex<(SystemTime, u64)>,
+    stop: AtomicBool,
+}
+
+fn file_stamp(path: &Path) -> Result<(SystemTime, u64), BugiError> {
+    let meta = std::fs::metadata(path).map_err(|e| BugiError::PluginLoadError(e.to_string()))?;
+    let modified = meta
+        .modified()
+        .map_err(|e| BugiError::PluginLoadError(e.to_string()))?;
+    Ok((modified, meta.len()))
+}
+
+impl Universe {
+    /// Add a wasm plugin and watch its file for changes.
+    /// The plugin is loaded with the default runtime and no limits.
+    pub fn add_wasm_watched(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(PluginRef, WasmWatcher), BugiError> {
+        self.add_wasm_watched_with(path, |path| WasmPlugin::load(path))
+    }
+
+    /// Add a wasm plugin and watch its file for changes.
+    /// `loader` loads the plugin from the file, the first time and on every reload,
+    /// so the runtime, limits and WASI setup it chooses survive reloads.
+    pub fn add_wasm_watched_with(
+        &self,
+        path: impl AsRef<Path>,
+        loader: impl Fn(&Path) -> anyhow::Result<WasmPlugin> + Send + Sync + 'static,
+    ) -> Result<(PluginRef, WasmWatcher), BugiError> {
+        let path = path.as_ref().to_path_buf();
+        let stamp = file_stamp(&path)?;
+        let wasm = loader(&path).map_err(|e| BugiError::PluginLoadError(e.to_string()))?;
+        let str_id = wasm.plugin_id().to_string();
+        let pref = self.add_plugin(wasm)?;
+
+        let watcher = WasmWatcher {
+            inner: Arc::new(WatcherInner {
+                univ: UniverseWeak(Arc::downgrade(&self.0)),
+                path,
+                loader: Box::new(loader),
+                str_id,
+                stamp: Mutex::new(stamp),
+                stop: AtomicBool::new(false),
+            }),
+            thread: None,
+        };
+
+        Ok((pref, watcher))
+    }
+}
+
+impl WasmWatcher {
+    /// Reload the plugin if the file changed since the last check.
+    /// Returns whether the plugin was reloaded.
+    pub fn check(&self) -> Result<bool, BugiError> {
+        self.inner.check()
+    }
+
+    /// Check the file every `interval` on a background thread.
+    /// Reload failures are passed to `on_error` and the old plugin is kept.
+    pub fn spawn(&mut self, interval: Duration, on_error: impl Fn(BugiError) + Send + 'static) {
+        self.stop();
+        self.inner.stop.store(false, Ordering::SeqCst);
+
+        let inner = Arc::clone(&self.inner);
+        self.thread = Some(std::thread::spawn(move || {
+            loop {
+                // `stop` unparks the thread, so it doesn't wait out the interval
+                let deadline = Instant::now() + interval;
+                while !inner.stop.load(Ordering::SeqCst) {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    std::thread::park_timeout(deadline - now);
+                }
+                if inner.stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Err(err) = inner.check() {
+                    on_error(err);
+                }
+            }
+        }));
+    }
+
+    /// Stop the background thread
+    pub fn stop(&mut self) {
+        self.inner.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+
+    /// The watched file
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+}
+
+impl Drop for WasmWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl WatcherInner {
+    fn check(&self) -> Result<bool, BugiError> {
+        let stamp = file_stamp(&self.path)?;
+        {
+            let mut last = self.stamp.lock().unwrap();
+            if *last == stamp {
+                return Ok(false);
+            }
+            // a broken file is reported once, not on every check
+            *last = stamp;
+        }
+
+        let univ = self
+            .univ
+            .upgrade()
+            .ok_or(BugiError::PluginUniverseDropped)?;
+
+        let wasm =
+            (self.loader)(&self.path).map_err(|e| BugiError::PluginLoadError(e.to_string()))?;
+        if wasm.plugin_id() != self.str_id {
+            return Err(BugiError::PluginIdMismatch(
+                self.str_id.clone(),
+                wasm.plugin_id().to_string(),
+            ));
+        }
+
+        univ.replace_plugin(wasm)?;
+        Ok(true)
+    }
+}
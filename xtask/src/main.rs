@@ -18,6 +18,8 @@ enum Cli {
 #[derive(Subcommand)]
 enum BugiCmd {
     Wasm,
+    Dylib,
+    Process,
 }
 
 const WASM_PLUGS: [&str; 1] = ["wasm-plug"];
@@ -31,6 +33,12 @@ fn main() {
             if !exists("./bugi/bugi-tests/wasm-plug.test.wasm") || force {
                 bugi_wasm_test_build();
             }
+            if !exists(&dylib_test_path()) || force {
+                bugi_dylib_test_build();
+            }
+            if !exists(&process_test_path()) || force {
+                bugi_process_test_build();
+            }
 
             out(Command::new("cargo")
                 .arg("nextest")
@@ -45,6 +53,12 @@ fn main() {
             BugiCmd::Wasm => {
                 bugi_wasm_test_build();
             }
+            BugiCmd::Dylib => {
+                bugi_dylib_test_build();
+            }
+            BugiCmd::Process => {
+                bugi_process_test_build();
+            }
         },
     }
 }
@@ -76,3 +90,53 @@ fn bugi_wasm_test_build() {
     )
     .unwrap();
 }
+
+fn dylib_test_path() -> String {
+    format!(
+        "./bugi/bugi-tests/dylib-plug.test{}",
+        std::env::consts::DLL_SUFFIX
+    )
+}
+
+fn bugi_dylib_test_build() {
+    out(Command::new("cargo")
+        .arg("build")
+        .args(["-p", "dylib-plug"])
+        .args(["-Z", "unstable-options"])
+        .args(["--artifact-dir", "./.test"])
+        .output()
+        .unwrap());
+
+    std::fs::copy(
+        format!(
+            "./.test/{}dylib_plug{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        ),
+        dylib_test_path(),
+    )
+    .unwrap();
+}
+
+fn process_test_path() -> String {
+    format!(
+        "./bugi/bugi-tests/process-plug.test{}",
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+fn bugi_process_test_build() {
+    out(Command::new("cargo")
+        .arg("build")
+        .args(["-p", "process-plug"])
+        .args(["-Z", "unstable-options"])
+        .args(["--artifact-dir", "./.test"])
+        .output()
+        .unwrap());
+
+    std::fs::copy(
+        format!("./.test/process-plug{}", std::env::consts::EXE_SUFFIX),
+        process_test_path(),
+    )
+    .unwrap();
+}